@@ -1,9 +1,13 @@
 use std::cmp;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::f64;
 use std::fmt;
 use std::io::{self, Write};
 use std::iter;
 use std::ops;
 
+use unicode_width::UnicodeWidthChar;
+
 use crate::style;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -37,6 +41,49 @@ impl Point {
         self.x = x;
         self.y = y;
     }
+
+    /// Returns the point shifted by the given offsets, if it stays within the grid.
+    pub fn offset(self, dx: i32, dy: i32) -> Option<Point> {
+        let shift = |value: u16, delta: i32| {
+            let shifted = i32::from(value) + delta;
+            if shifted < 1 || shifted > i32::from(u16::MAX) {
+                None
+            } else {
+                Some(shifted as u16)
+            }
+        };
+
+        Some(Point::new(shift(self.x, dx)?, shift(self.y, dy)?))
+    }
+
+    /// Returns the number of horizontal and vertical steps between the points.
+    pub fn manhattan_distance(self, other: Point) -> u32 {
+        u32::from(self.x.abs_diff(other.x)) + u32::from(self.y.abs_diff(other.y))
+    }
+
+    /// Returns the straight line distance between the points.
+    pub fn distance(self, other: Point) -> f64 {
+        f64::from(self.x.abs_diff(other.x)).hypot(f64::from(self.y.abs_diff(other.y)))
+    }
+
+    /// Returns the index of the point in a row-major buffer of the given width. Points are
+    /// 1-based while indexes are 0-based, so `(1, 1)` maps to `0`.
+    pub fn to_index(self, width: u16) -> usize {
+        let (x, y) = (usize::from(self.x.saturating_sub(1)), usize::from(self.y.saturating_sub(1)));
+        y * usize::from(width) + x
+    }
+
+    /// Returns the point at the given index of a row-major buffer of the given width, being the
+    /// inverse of `to_index`. A zero width yields the origin.
+    pub fn from_index(index: usize, width: u16) -> Point {
+        if width == 0 {
+            return Point::default();
+        }
+
+        let width = usize::from(width);
+        let coordinate = |value: usize| (value + 1).min(usize::from(u16::MAX)) as u16;
+        Point::new(coordinate(index % width), coordinate(index / width))
+    }
 }
 
 impl Default for Point {
@@ -57,7 +104,7 @@ impl fmt::Display for Point {
     }
 }
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Cell {
     pos: Point,
     content: char,
@@ -68,6 +115,14 @@ impl Cell {
         Self { pos, content }
     }
 
+    pub fn pos(&self) -> Point {
+        self.pos
+    }
+
+    pub fn content(&self) -> char {
+        self.content
+    }
+
     pub fn clear(&mut self) {
         self.content = ' ';
     }
@@ -79,6 +134,34 @@ impl fmt::Display for Cell {
     }
 }
 
+pub const DEFAULT_TAB_WIDTH: u16 = 4;
+
+// Number of cells along a trace sharing the same jitter
+const JITTER_RUN: i32 = 4;
+
+/// Returns the number of columns `Segment::from_str` lays the text out on, being those of its
+/// widest line, with wide characters taking up two columns.
+pub fn text_width(str: &str) -> u16 {
+    text_width_with_tab_width(str, DEFAULT_TAB_WIDTH)
+}
+
+/// Like `text_width`, but for text laid out by `Segment::from_str_with_tab_width`.
+pub fn text_width_with_tab_width(str: &str, tab_width: u16) -> u16 {
+    let line_width = |line: &str| {
+        line.chars().fold(0, |width, char| match char {
+            '\r' => width,
+            '\t' if tab_width == 0 => width + 1,
+            '\t' => width + tab_width - width % tab_width,
+            _ => width + char_width(char),
+        })
+    };
+    str.split('\n').map(line_width).max().unwrap_or_default()
+}
+
+fn char_width(char: char) -> u16 {
+    char.width().map_or(1, |width| cmp::max(width, 1) as u16)
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Segment {
     cells: Vec<Cell>,
@@ -90,29 +173,351 @@ impl Segment {
         Self { cells: Vec::new(), format: Default::default() }
     }
 
+    /// Returns the cells drawing each glyph at its point, in order.
+    pub fn from_points<I: IntoIterator<Item = (Point, char)>>(points: I) -> Self {
+        points.into_iter().map(|(pos, content)| Cell::new(pos, content)).collect()
+    }
+
+    /// Returns the text written from `start`, each new line starting below the previous one at
+    /// the same column.
     pub fn from_str(start: Point, str: &str, format: style::Format) -> Self {
+        Self::from_str_with_tab_width(start, str, format, DEFAULT_TAB_WIDTH)
+    }
+
+    /// Like `from_str`, but expands tabs with spaces up to the next multiple of `tab_width`
+    /// columns from the start.
+    pub fn from_str_with_tab_width(
+        start: Point,
+        str: &str,
+        format: style::Format,
+        tab_width: u16,
+    ) -> Self {
         let mut cells = Vec::new();
         let mut cursor = start;
-        for char in str.as_bytes() {
-            cells.push(Cell::new(cursor, (*char) as char));
-            cursor.move_right();
+        for char in str.chars() {
+            match char {
+                '\n' => cursor.move_to(start.x, cursor.y + 1),
+                '\r' => {}
+                '\t' => loop {
+                    cells.push(Cell::new(cursor, ' '));
+                    cursor.move_right();
+                    if tab_width == 0 || (cursor.x - start.x).is_multiple_of(tab_width) {
+                        break;
+                    }
+                },
+                _ => {
+                    cells.push(Cell::new(cursor, char));
+                    cursor.x += char_width(char);
+                }
+            }
         }
 
         Self { cells, format }
     }
 
+    /// Returns a grid of `cols` by `rows` boxes drawn with box drawing characters, each box
+    /// holding `cell_w` by `cell_h` blank cells. Returns nothing if it wouldn't fit in the grid.
+    pub fn grid(top_left: Point, cell_w: u16, cell_h: u16, cols: u16, rows: u16) -> Option<Self> {
+        let span = |start: u16, size: u16, count: u16| {
+            let end = u32::from(start) + (u32::from(size) + 1) * u32::from(count);
+            if end > u32::from(u16::MAX) {
+                None
+            } else {
+                Some(end as u16)
+            }
+        };
+
+        let end = Point::new(span(top_left.x, cell_w, cols)?, span(top_left.y, cell_h, rows)?);
+        let on_column = |x: u16| (x - top_left.x).is_multiple_of(cell_w + 1);
+        let on_row = |y: u16| (y - top_left.y).is_multiple_of(cell_h + 1);
+
+        let segment = (top_left.y..=end.y)
+            .flat_map(|y| (top_left.x..=end.x).map(move |x| Point::new(x, y)))
+            .filter(|pos| on_column(pos.x) || on_row(pos.y))
+            .map(|pos| {
+                let glyph = box_glyph(
+                    on_column(pos.x) && pos.y > top_left.y,
+                    on_column(pos.x) && pos.y < end.y,
+                    on_row(pos.y) && pos.x > top_left.x,
+                    on_row(pos.y) && pos.x < end.x,
+                );
+                Cell::new(pos, glyph)
+            })
+            .collect();
+
+        Some(segment)
+    }
+
+    /// Fills the rectangle with the given opposite corners using the pattern.
+    pub fn filled_rect(a: Point, b: Point, pattern: FillPattern) -> Self {
+        let (start, end) = (
+            Point::new(cmp::min(a.x, b.x), cmp::min(a.y, b.y)),
+            Point::new(cmp::max(a.x, b.x), cmp::max(a.y, b.y)),
+        );
+
+        (start.y..=end.y)
+            .flat_map(|y| (start.x..=end.x).map(move |x| Point::new(x, y)))
+            .filter_map(|pos| Some(Cell::new(pos, pattern.glyph_at(pos)?)))
+            .collect()
+    }
+
+    /// Fills the polygon with the given vertices using the pattern, including its edges.
+    pub fn filled_polygon(vertices: &[Point], pattern: FillPattern) -> Self {
+        let (start, end) =
+            match vertices.iter().map(|pos| Cell::new(*pos, ' ')).collect::<Segment>().boundaries()
+            {
+                Some(boundaries) => boundaries,
+                None => return Self::new(),
+            };
+
+        (start.y..=end.y)
+            .flat_map(|y| (start.x..=end.x).map(move |x| Point::new(x, y)))
+            .filter(|pos| encloses(vertices, *pos))
+            .filter_map(|pos| Some(Cell::new(pos, pattern.glyph_at(pos)?)))
+            .collect()
+    }
+
     pub fn add(&mut self, cell: Cell) {
         self.cells.push(cell);
     }
 
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
     pub fn clear(&mut self) {
         self.cells.clear();
     }
 
+    /// Removes the cells drawn from index `at` onwards, returning them as a new segment.
+    pub fn split_off(&mut self, at: usize) -> Segment {
+        Self { cells: self.cells.split_off(cmp::min(at, self.cells.len())), format: self.format }
+    }
+
+    pub fn fill(&mut self, content: char) {
+        self.cells.iter_mut().for_each(|cell| cell.content = content);
+    }
+
+    pub fn format(&self) -> style::Format {
+        self.format
+    }
+
     pub fn set_format(&mut self, format: style::Format) {
         self.format = format;
     }
 
+    /// Returns a copy of the segment shifted by the given offsets, if all of its cells stay
+    /// within the grid.
+    pub fn translated(&self, dx: i32, dy: i32) -> Option<Segment> {
+        let cells = self
+            .cells
+            .iter()
+            .map(|cell| Some(Cell::new(cell.pos.offset(dx, dy)?, cell.content)))
+            .collect::<Option<Vec<Cell>>>()?;
+
+        Some(Self { cells, format: self.format })
+    }
+
+    /// Returns a copy of the segment reflected across the column `axis`, with asymmetric glyphs
+    /// swapped for their mirror image. Returns `None` if any cell would leave the grid.
+    pub fn flip_horizontal(&self, axis: u16) -> Option<Segment> {
+        let cells = self
+            .cells
+            .iter()
+            .map(|cell| {
+                let dx = 2 * (i32::from(axis) - i32::from(cell.pos.x));
+                Some(Cell::new(cell.pos.offset(dx, 0)?, mirror_glyph(cell.content)))
+            })
+            .collect::<Option<Vec<Cell>>>()?;
+
+        Some(Self { cells, format: self.format })
+    }
+
+    /// Returns a copy of the segment turned a quarter clockwise within the rectangle with the given
+    /// corners, keeping its top left corner in place. Glyphs are turned along with it. Returns
+    /// `None` if any cell would leave the grid.
+    pub fn rotate_90(&self, start: Point, end: Point) -> Option<Segment> {
+        let cells = self
+            .cells
+            .iter()
+            .map(|cell| {
+                let dx = i32::from(start.x) + i32::from(end.y) - i32::from(cell.pos.y);
+                let dy = i32::from(start.y) + i32::from(cell.pos.x) - i32::from(start.x);
+                let pos = Point::new(1, 1).offset(dx - 1, dy - 1)?;
+                Some(Cell::new(pos, rotate_glyph(cell.content)))
+            })
+            .collect::<Option<Vec<Cell>>>()?;
+
+        Some(Self { cells, format: self.format })
+    }
+
+    /// Returns the cells enclosed by the polygon with the given vertices, which is closed
+    /// automatically. Membership follows the even-odd rule, with the edges themselves included.
+    pub fn within_polygon(&self, vertices: &[Point]) -> Segment {
+        let cells =
+            self.cells.iter().filter(|cell| encloses(vertices, cell.pos)).copied().collect();
+
+        Self { cells, format: self.format }
+    }
+
+    /// Returns a copy of the segment with characters swapped for their substitutes, if any.
+    pub fn substituted(&self, substitutions: &HashMap<char, char>) -> Segment {
+        let cells = self
+            .cells
+            .iter()
+            .map(|cell| {
+                Cell::new(cell.pos, *substitutions.get(&cell.content).unwrap_or(&cell.content))
+            })
+            .collect();
+
+        Self { cells, format: self.format }
+    }
+
+    /// Returns whether every cell lies on a grid of `width` columns by `height` rows.
+    pub fn in_bounds(&self, width: u16, height: u16) -> bool {
+        self.cells
+            .iter()
+            .all(|cell| (1..=width).contains(&cell.pos.x) && (1..=height).contains(&cell.pos.y))
+    }
+
+    /// Returns the cells lying on a grid of `width` columns by `height` rows, dropping the rest.
+    pub fn clamped_to(&self, width: u16, height: u16) -> Segment {
+        if width == 0 || height == 0 {
+            return Self { cells: Vec::new(), format: self.format };
+        }
+
+        self.within_rect(Point::new(1, 1), Point::new(width, height))
+    }
+
+    /// Returns the cells within the rectangle with the given corners, edges included.
+    pub fn within_rect(&self, a: Point, b: Point) -> Segment {
+        let (xs, ys) =
+            (cmp::min(a.x, b.x)..=cmp::max(a.x, b.x), cmp::min(a.y, b.y)..=cmp::max(a.y, b.y));
+        let cells = self
+            .cells
+            .iter()
+            .filter(|cell| xs.contains(&cell.pos.x) && ys.contains(&cell.pos.y))
+            .copied()
+            .collect();
+
+        Self { cells, format: self.format }
+    }
+
+    /// Returns the first and last points drawn.
+    pub fn endpoints(&self) -> Option<(Point, Point)> {
+        Some((self.cells.first()?.pos, self.cells.last()?.pos))
+    }
+
+    /// Returns the length of the path through the cells in the order they were drawn, which is
+    /// longer than the cell count for diagonal runs.
+    pub fn path_length(&self) -> f64 {
+        self.cells.windows(2).map(|pair| pair[0].pos.distance(pair[1].pos)).sum()
+    }
+
+    /// Returns the largest distance of any cell from the straight line through `from` and `to`.
+    pub fn max_deviation(&self, from: Point, to: Point) -> f64 {
+        let (fx, fy) = (f64::from(from.x), f64::from(from.y));
+        let (dx, dy) = (f64::from(to.x) - fx, f64::from(to.y) - fy);
+        let length = dx.hypot(dy);
+
+        self.cells
+            .iter()
+            .map(|cell| {
+                let (cx, cy) = (f64::from(cell.pos.x) - fx, f64::from(cell.pos.y) - fy);
+                if length == 0.0 {
+                    cx.hypot(cy)
+                } else {
+                    (dx * cy - dy * cx).abs() / length
+                }
+            })
+            .fold(0.0, f64::max)
+    }
+
+    pub fn difference(&self, other: &Segment) -> Segment {
+        let cells = self
+            .cells
+            .iter()
+            .filter(|cell| !other.cells.iter().any(|other_cell| other_cell.pos == cell.pos))
+            .copied()
+            .collect();
+
+        Self { cells, format: self.format }
+    }
+
+    /// Writes the cell contents into a row-major buffer, where `buf[0][0]` is the point (1, 1).
+    /// Cells falling outside the buffer are skipped.
+    pub fn render_into(&self, buf: &mut [Vec<char>]) {
+        for cell in &self.cells {
+            let (x, y) = (cell.pos.x as usize, cell.pos.y as usize);
+            if x == 0 || y == 0 {
+                continue;
+            }
+
+            if let Some(slot) = buf.get_mut(y - 1).and_then(|row| row.get_mut(x - 1)) {
+                *slot = cell.content;
+            }
+        }
+    }
+
+    /// Returns the visible cells only, being the last one drawn at each point unless it's blank.
+    pub fn trimmed(&self) -> Segment {
+        let mut segment = self.deduplicated();
+        segment.cells.retain(|cell| cell.content != ' ');
+        segment
+    }
+
+    /// Returns whether the segments have any point in common.
+    pub fn intersects(&self, other: &Segment) -> bool {
+        let points: HashSet<Point> = self.cells.iter().map(|cell| cell.pos).collect();
+        other.cells.iter().any(|cell| points.contains(&cell.pos))
+    }
+
+    /// Returns a copy of the segment keeping only the last cell drawn at each point.
+    pub fn deduplicated(&self) -> Segment {
+        let mut seen = HashSet::new();
+        let mut cells: Vec<Cell> =
+            self.cells.iter().rev().filter(|cell| seen.insert(cell.pos)).copied().collect();
+        cells.reverse();
+
+        Self { cells, format: self.format }
+    }
+
+    /// Returns the points drawn more than once, each reported once in the order they're redrawn.
+    pub fn duplicates(&self) -> Vec<Point> {
+        let mut seen = HashSet::new();
+        let mut reported = HashSet::new();
+        self.cells
+            .iter()
+            .filter(|cell| !seen.insert(cell.pos) && reported.insert(cell.pos))
+            .map(|cell| cell.pos)
+            .collect()
+    }
+
+    /// Groups consecutively drawn cells into straight runs of the same glyph.
+    pub fn compact(&self) -> CompactSegment {
+        let mut runs: Vec<Run> = Vec::new();
+        for cell in &self.cells {
+            if let Some(run) = runs.last_mut() {
+                if run.glyph == cell.content && run.extends_to(cell.pos) {
+                    run.end = cell.pos;
+                    continue;
+                }
+            }
+
+            runs.push(Run { start: cell.pos, end: cell.pos, glyph: cell.content });
+        }
+
+        CompactSegment { runs, format: self.format }
+    }
+
     pub fn boundaries(&self) -> Option<(Point, Point)> {
         if self.cells.is_empty() {
             return None;
@@ -134,6 +539,55 @@ impl Segment {
     }
 }
 
+/// A straight horizontal, vertical or diagonal line of a single glyph, drawn from start to end.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Run {
+    pub start: Point,
+    pub end: Point,
+    pub glyph: char,
+}
+
+impl Run {
+    fn step(&self) -> (i32, i32) {
+        (
+            (i32::from(self.end.x) - i32::from(self.start.x)).signum(),
+            (i32::from(self.end.y) - i32::from(self.start.y)).signum(),
+        )
+    }
+
+    fn extends_to(&self, pos: Point) -> bool {
+        let (dx, dy) =
+            (i32::from(pos.x) - i32::from(self.end.x), i32::from(pos.y) - i32::from(self.end.y));
+
+        if dx.abs() > 1 || dy.abs() > 1 || (dx, dy) == (0, 0) {
+            return false;
+        }
+
+        self.start == self.end || self.step() == (dx, dy)
+    }
+
+    fn cells(&self) -> impl Iterator<Item = Cell> + '_ {
+        let (dx, dy) = self.step();
+        let length = cmp::max(self.start.x.abs_diff(self.end.x), self.start.y.abs_diff(self.end.y));
+        (0..=i32::from(length))
+            .filter_map(move |i| Some(Cell::new(self.start.offset(dx * i, dy * i)?, self.glyph)))
+    }
+}
+
+/// A segment stored as runs rather than individual cells, see `Segment::compact`.
+#[derive(Debug, Default, Clone)]
+pub struct CompactSegment {
+    pub runs: Vec<Run>,
+    pub format: style::Format,
+}
+
+impl CompactSegment {
+    pub fn expand(&self) -> Segment {
+        let cells = self.runs.iter().flat_map(Run::cells).collect();
+        Segment { cells, format: self.format }
+    }
+}
+
 impl From<Segment> for String {
     fn from(segment: Segment) -> Self {
         let mut output = "".to_string();
@@ -163,6 +617,12 @@ impl From<Segment> for String {
     }
 }
 
+impl iter::FromIterator<Cell> for Segment {
+    fn from_iter<I: IntoIterator<Item = Cell>>(iter: I) -> Self {
+        Self { cells: iter.into_iter().collect(), format: Default::default() }
+    }
+}
+
 impl<'a> iter::Sum<&'a Segment> for Segment {
     fn sum<I: Iterator<Item = &'a Segment>>(iter: I) -> Self {
         let mut result = Segment::new();
@@ -183,14 +643,122 @@ impl ops::AddAssign for Segment {
 impl fmt::Display for Segment {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.format)?;
-        for cell in &self.cells {
-            write!(f, "{}", cell)?;
+
+        // Cells are written row by row, only moving the cursor where the terminal advancing past
+        // the previous character doesn't already put it in place
+        let mut cells = self.deduplicated().cells;
+        cells.sort_by_key(|cell| (cell.pos.y, cell.pos.x));
+
+        let mut cursor = None;
+        for cell in &cells {
+            if cursor != Some(cell.pos) {
+                write!(f, "{}", cell.pos)?;
+            }
+            write!(f, "{}", cell.content)?;
+            cursor =
+                Some(Point::new(cell.pos.x.saturating_add(char_width(cell.content)), cell.pos.y));
         }
         write!(f, "{}", style::RESET_FORMAT)?;
         Ok(())
     }
 }
 
+fn encloses(vertices: &[Point], point: Point) -> bool {
+    let (x, y) = (f64::from(point.x), f64::from(point.y));
+    let mut inside = false;
+
+    for (i, a) in vertices.iter().enumerate() {
+        let b = vertices[(i + 1) % vertices.len()];
+        let (ax, ay, bx, by) = (f64::from(a.x), f64::from(a.y), f64::from(b.x), f64::from(b.y));
+
+        let on_edge = (bx - ax) * (y - ay) == (by - ay) * (x - ax)
+            && x >= ax.min(bx)
+            && x <= ax.max(bx)
+            && y >= ay.min(by)
+            && y <= ay.max(by);
+        if on_edge {
+            return true;
+        }
+
+        if (ay > y) != (by > y) && x < (bx - ax) * (y - ay) / (by - ay) + ax {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+fn mirror_glyph(glyph: char) -> char {
+    match glyph {
+        '/' => '\\',
+        '\\' => '/',
+        '<' => '>',
+        '>' => '<',
+        '(' => ')',
+        ')' => '(',
+        '[' => ']',
+        ']' => '[',
+        '{' => '}',
+        '}' => '{',
+        _ => glyph,
+    }
+}
+
+fn rotate_glyph(glyph: char) -> char {
+    match glyph {
+        '_' | '-' => '|',
+        '|' => '_',
+        '/' => '\\',
+        '\\' => '/',
+        '>' => 'v',
+        'v' => '<',
+        '<' => '^',
+        '^' => '>',
+        '─' => '│',
+        '│' => '─',
+        '┌' => '┐',
+        '┐' => '┘',
+        '┘' => '└',
+        '└' => '┌',
+        '├' => '┬',
+        '┬' => '┤',
+        '┤' => '┴',
+        '┴' => '├',
+        _ => glyph,
+    }
+}
+
+/// Compares the visible cells of two snapshots, returning the cells added and removed by `new`.
+pub fn diff_snapshots(old: &[Segment], new: &[Segment]) -> (Vec<Cell>, Vec<Cell>) {
+    let old = old.iter().sum::<Segment>().trimmed();
+    let new = new.iter().sum::<Segment>().trimmed();
+
+    let old_cells: HashSet<Cell> = old.cells.iter().copied().collect();
+    let new_cells: HashSet<Cell> = new.cells.iter().copied().collect();
+
+    let added = new.cells.iter().filter(|cell| !old_cells.contains(cell)).copied().collect();
+    let removed = old.cells.iter().filter(|cell| !new_cells.contains(cell)).copied().collect();
+
+    (added, removed)
+}
+
+/// Returns the box drawing character joining lines in the given directions.
+fn box_glyph(up: bool, down: bool, left: bool, right: bool) -> char {
+    match (up, down, left, right) {
+        (true, true, true, true) => '┼',
+        (false, true, true, true) => '┬',
+        (true, false, true, true) => '┴',
+        (true, true, false, true) => '├',
+        (true, true, true, false) => '┤',
+        (false, true, false, true) => '┌',
+        (false, true, true, false) => '┐',
+        (true, false, false, true) => '└',
+        (true, false, true, false) => '┘',
+        (_, _, false, false) => '│',
+        _ => '─',
+    }
+}
+
 pub trait Erase {
     fn erase(&mut self, segment: &mut Segment) -> io::Result<()>;
 }
@@ -244,38 +812,578 @@ impl Default for CharSet {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FillPattern {
+    Solid(char),
+    /// Alternates between the two glyphs, starting with the first where `x + y` is even.
+    Checker(char, char),
+    /// Diagonal lines of the glyph, drawn where `x + y` is a multiple of the spacing.
+    Hatch(char, u16),
+}
+
+impl FillPattern {
+    /// Returns the glyph to fill the point with, or `None` if it should be left untouched.
+    pub fn glyph_at(&self, pos: Point) -> Option<char> {
+        let sum = u32::from(pos.x) + u32::from(pos.y);
+        match *self {
+            FillPattern::Solid(glyph) => Some(glyph),
+            FillPattern::Checker(even, odd) => Some(if sum % 2 == 0 { even } else { odd }),
+            FillPattern::Hatch(glyph, spacing) => {
+                if sum.is_multiple_of(u32::from(cmp::max(spacing, 1))) {
+                    Some(glyph)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// How a trace steps towards a point that isn't in line with its start.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum PathMode {
+    /// Steps diagonally until in line with the end, then straight to it.
+    #[default]
+    Diagonal,
+    /// Steps horizontally until in line with the end, then vertically to it.
+    AxisFirst,
+    /// Steps straight along the axis furthest from the end until the remaining distances are
+    /// equal, then diagonally to it.
+    DominantFirst,
+}
+
+pub type GlyphSelector = Box<dyn Fn(Point, Point) -> char>;
+
 pub struct Tracer {
-    char_set: CharSet,
+    glyph: GlyphSelector,
+    spacing: u16,
+    jitter: Option<(u64, u16)>,
+    path_mode: PathMode,
 }
 
 impl Tracer {
     pub fn new(char_set: CharSet) -> Self {
-        Self { char_set }
+        Self::from_fn(move |from, to| char_set.next(from, to))
+    }
+
+    pub fn from_fn<F>(glyph: F) -> Self
+    where
+        F: Fn(Point, Point) -> char + 'static,
+    {
+        Self { glyph: Box::new(glyph), spacing: 1, jitter: None, path_mode: Default::default() }
+    }
+
+    /// Only draws every `spacing`-th step of a trace, for stippled paths.
+    pub fn with_spacing(mut self, spacing: u16) -> Self {
+        self.spacing = cmp::max(spacing, 1);
+        self
+    }
+
+    /// Shifts traced cells by up to `amount` cells across the direction of the trace, for a hand
+    /// drawn look. Shifts are derived from `seed` and the cell position, so they're reproducible.
+    pub fn with_jitter(mut self, seed: u64, amount: u16) -> Self {
+        self.jitter = Some((seed, amount));
+        self
+    }
+
+    pub fn with_path_mode(mut self, path_mode: PathMode) -> Self {
+        self.path_mode = path_mode;
+        self
+    }
+
+    fn jittered(&self, from: Point, to: Point) -> Point {
+        let (seed, amount) = match self.jitter {
+            Some((_, 0)) | None => return to,
+            Some(jitter) => jitter,
+        };
+
+        let (dx, dy) = (i32::from(to.x) - i32::from(from.x), i32::from(to.y) - i32::from(from.y));
+        let (x, y) = (i32::from(to.x), i32::from(to.y));
+        // Keep the shift for a few cells along the trace, so it wobbles rather than scatters
+        let (along, across) = ((x * dx + y * dy).div_euclid(JITTER_RUN), y * dx - x * dy);
+
+        // SplitMix64 finalizer over the seed and position
+        let mut hash = seed ^ ((along as u32 as u64) << 32 | across as u32 as u64);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^= hash >> 31;
+
+        let shift = (hash % (2 * u64::from(amount) + 1)) as i32 - i32::from(amount);
+        to.offset(-dy * shift, dx * shift).unwrap_or(to)
     }
 
     pub fn trace(&self, from: Point, to: Point) -> Segment {
         let mut segment = Segment::new();
         let mut cursor = from;
+        let mut step: u16 = 0;
 
         while cursor != to {
             let current_pos = cursor;
+            step = step.wrapping_add(1);
 
-            match cursor.y.cmp(&to.y) {
-                cmp::Ordering::Greater => cursor.move_up(),
-                cmp::Ordering::Less => cursor.move_down(),
-                _ => {}
+            let (dx, dy) = (cursor.x.abs_diff(to.x), cursor.y.abs_diff(to.y));
+            let (step_x, step_y) = match self.path_mode {
+                PathMode::Diagonal => (true, true),
+                PathMode::AxisFirst => (dx > 0, dx == 0),
+                PathMode::DominantFirst => (dx >= dy, dy >= dx),
             };
 
-            match cursor.x.cmp(&to.x) {
-                cmp::Ordering::Greater => cursor.move_left(),
-                cmp::Ordering::Less => cursor.move_right(),
-                _ => {}
-            };
+            if step_y {
+                match cursor.y.cmp(&to.y) {
+                    cmp::Ordering::Greater => cursor.move_up(),
+                    cmp::Ordering::Less => cursor.move_down(),
+                    _ => {}
+                };
+            }
+
+            if step_x {
+                match cursor.x.cmp(&to.x) {
+                    cmp::Ordering::Greater => cursor.move_left(),
+                    cmp::Ordering::Less => cursor.move_right(),
+                    _ => {}
+                };
+            }
+
+            if step.is_multiple_of(self.spacing) {
+                let pos = self.jittered(current_pos, cursor);
+                segment.add(Cell::new(pos, (self.glyph)(current_pos, cursor)));
+            }
+        }
+
+        segment
+    }
+
+    pub fn trace_path(&self, points: &[Point]) -> Segment {
+        points.windows(2).map(|pair| self.trace(pair[0], pair[1])).fold(
+            Segment::new(),
+            |mut path, leg| {
+                path += leg;
+                path
+            },
+        )
+    }
+
+    /// Draws the outline of the rectangle with the given opposite corners. With spacing, the
+    /// edges are dashed but the corners are always drawn.
+    pub fn rectangle(&self, a: Point, b: Point) -> Segment {
+        let (start, end) = (
+            Point::new(cmp::min(a.x, b.x), cmp::min(a.y, b.y)),
+            Point::new(cmp::max(a.x, b.x), cmp::max(a.y, b.y)),
+        );
+
+        let corners = [start, Point::new(end.x, start.y), end, Point::new(start.x, end.y), start];
+        let mut rectangle = self.trace_path(&corners);
+
+        // Spaced out edges still get their corners, so that the shape stays readable
+        for leg in corners.windows(2).filter(|leg| leg[0] != leg[1]) {
+            if !rectangle.cells.iter().any(|cell| cell.pos == leg[1]) {
+                rectangle.add(Cell::new(leg[1], (self.glyph)(leg[0], leg[1])));
+            }
+        }
+        rectangle
+    }
+
+    /// Draws a closed regular polygon, with its first vertex above the center. The horizontal
+    /// radius is doubled to make up for terminal cells being about twice as tall as they are wide.
+    /// Returns `None` if there are fewer than 3 sides, or the polygon doesn't fit on the grid.
+    pub fn polygon(&self, center: Point, radius: u16, sides: u16) -> Option<Segment> {
+        if sides < 3 {
+            return None;
+        }
+
+        let mut vertices = (0..sides)
+            .map(|side| {
+                let angle = f64::from(side) * 2.0 * f64::consts::PI / f64::from(sides)
+                    - f64::consts::FRAC_PI_2;
+                let dx = (2.0 * f64::from(radius) * angle.cos()).round() as i32;
+                let dy = (f64::from(radius) * angle.sin()).round() as i32;
+                center.offset(dx, dy)
+            })
+            .collect::<Option<Vec<Point>>>()?;
+        vertices.push(vertices[0]);
+
+        Some(self.trace_path(&vertices))
+    }
+
+    /// Draws an orthogonal connector, ending in an arrowhead, between the facing edges of two
+    /// rectangular segments. Returns `None` if the segments overlap or touch.
+    pub fn connect_boxes(&self, a: &Segment, b: &Segment) -> Option<Segment> {
+        let (a_start, a_end) = a.boundaries()?;
+        let (b_start, b_end) = b.boundaries()?;
+
+        let (from, to, arrow) = if a_end.x + 1 < b_start.x {
+            let from = Point::new(a_end.x + 1, (a_start.y + a_end.y) / 2);
+            (from, Point::new(b_start.x - 1, (b_start.y + b_end.y) / 2), '>')
+        } else if b_end.x + 1 < a_start.x {
+            let from = Point::new(a_start.x - 1, (a_start.y + a_end.y) / 2);
+            (from, Point::new(b_end.x + 1, (b_start.y + b_end.y) / 2), '<')
+        } else if a_end.y + 1 < b_start.y {
+            let from = Point::new((a_start.x + a_end.x) / 2, a_end.y + 1);
+            (from, Point::new((b_start.x + b_end.x) / 2, b_start.y - 1), 'v')
+        } else if b_end.y + 1 < a_start.y {
+            let from = Point::new((a_start.x + a_end.x) / 2, a_start.y - 1);
+            (from, Point::new((b_start.x + b_end.x) / 2, b_end.y + 1), '^')
+        } else {
+            return None;
+        };
+
+        let (first_bend, second_bend) = match arrow {
+            '>' | '<' => {
+                let x = (from.x + to.x) / 2;
+                (Point::new(x, from.y), Point::new(x, to.y))
+            }
+            _ => {
+                let y = (from.y + to.y) / 2;
+                (Point::new(from.x, y), Point::new(to.x, y))
+            }
+        };
+
+        let mut connector = Segment::new();
+        let heading = [first_bend, second_bend, to].iter().find(|p| **p != from).copied();
+        connector.add(Cell::new(from, (self.glyph)(from, heading.unwrap_or(from))));
+        connector += self.trace(from, first_bend);
+        connector += self.trace(first_bend, second_bend);
+        connector += self.trace(second_bend, to);
+        connector.cells.retain(|cell| cell.pos != to);
+        connector.add(Cell::new(to, arrow));
+
+        Some(connector)
+    }
+
+    /// Traces an orthogonal path from `from` to `to` that goes around the `obstacles`, staying
+    /// within the rectangle with the given corners. Among the shortest paths, the one with the
+    /// fewest turns is preferred. Returns nothing if there's no way through.
+    pub fn route(
+        &self,
+        from: Point,
+        to: Point,
+        obstacles: &HashSet<Point>,
+        area: (Point, Point),
+    ) -> Option<Segment> {
+        const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+        let (start, end) = (
+            Point::new(cmp::min(area.0.x, area.1.x), cmp::min(area.0.y, area.1.y)),
+            Point::new(cmp::max(area.0.x, area.1.x), cmp::max(area.0.y, area.1.y)),
+        );
+        let passable = |pos: Point| {
+            (start.x..=end.x).contains(&pos.x)
+                && (start.y..=end.y).contains(&pos.y)
+                && (pos == to || !obstacles.contains(&pos))
+        };
+
+        // Dijkstra over positions and headings, so that turns can be counted. Costs are compared
+        // by steps, then by turns.
+        let mut costs: HashMap<(Point, usize), (u32, u32)> = HashMap::new();
+        let mut previous: HashMap<(Point, usize), (Point, usize)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for heading in 0..DIRECTIONS.len() {
+            costs.insert((from, heading), (0, 0));
+            queue.push(cmp::Reverse(((0, 0), from.x, from.y, heading)));
+        }
+
+        let mut arrival = None;
+        while let Some(cmp::Reverse((cost, x, y, heading))) = queue.pop() {
+            let pos = Point::new(x, y);
+            if pos == to {
+                arrival = Some((pos, heading));
+                break;
+            }
+            if costs.get(&(pos, heading)).is_some_and(|best| cost > *best) {
+                continue;
+            }
+
+            for (next_heading, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                let next = match pos.offset(*dx, *dy) {
+                    Some(next) if passable(next) => next,
+                    _ => continue,
+                };
+
+                let turn = if next_heading == heading { 0 } else { 1 };
+                let next_cost = (cost.0 + 1, cost.1 + turn);
+                if costs.get(&(next, next_heading)).is_none_or(|best| next_cost < *best) {
+                    costs.insert((next, next_heading), next_cost);
+                    previous.insert((next, next_heading), (pos, heading));
+                    queue.push(cmp::Reverse((next_cost, next.x, next.y, next_heading)));
+                }
+            }
+        }
 
-            segment.add(Cell::new(cursor, self.char_set.next(current_pos, cursor)));
+        let mut path = vec![arrival?];
+        while let Some(state) = previous.get(path.last()?) {
+            path.push(*state);
         }
+        let points: Vec<Point> = path.iter().rev().map(|(pos, _)| *pos).collect();
+
+        let mut route = Segment::new();
+        if let Some(next) = points.get(1) {
+            route.add(Cell::new(from, (self.glyph)(from, *next)));
+        }
+        points
+            .windows(2)
+            .for_each(|pair| route.add(Cell::new(pair[1], (self.glyph)(pair[0], pair[1]))));
+        Some(route)
+    }
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
 
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("spacing", &self.spacing)
+            .field("jitter", &self.jitter)
+            .field("path_mode", &self.path_mode)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(cells: &[(u16, u16, char)]) -> Segment {
+        let mut segment = Segment::new();
+        cells.iter().for_each(|(x, y, glyph)| segment.add(Cell::new(Point::new(*x, *y), *glyph)));
         segment
     }
+
+    fn cells(segment: &Segment) -> Vec<(u16, u16, char)> {
+        segment.cells.iter().map(|cell| (cell.pos.x, cell.pos.y, cell.content)).collect()
+    }
+
+    #[test]
+    fn difference_drops_cells_at_other_points() {
+        let drawing = segment(&[(1, 1, 'a'), (2, 1, 'b'), (3, 1, 'c')]);
+        let eraser = segment(&[(2, 1, ' '), (9, 9, ' ')]);
+        assert_eq!(cells(&drawing.difference(&eraser)), vec![(1, 1, 'a'), (3, 1, 'c')]);
+    }
+
+    #[test]
+    fn from_fn_tracer_draws_with_given_glyph() {
+        let line = Tracer::from_fn(|_, _| '#').trace(Point::new(1, 1), Point::new(4, 1));
+        assert_eq!(cells(&line), vec![(2, 1, '#'), (3, 1, '#'), (4, 1, '#')]);
+    }
+
+    #[test]
+    fn connect_boxes_joins_facing_edges() {
+        let tracer = Tracer::default();
+        let a = segment(&[(1, 1, '+'), (4, 3, '+')]);
+        let b = segment(&[(10, 5, '+'), (13, 7, '+')]);
+        let connector = cells(&tracer.connect_boxes(&a, &b).unwrap());
+
+        assert_eq!(connector.first(), Some(&(5, 2, '_')));
+        assert_eq!(connector.last(), Some(&(9, 6, '>')));
+        let orthogonal =
+            connector.windows(2).all(|pair| pair[0].0 == pair[1].0 || pair[0].1 == pair[1].1);
+        assert!(orthogonal);
+        assert!(tracer.connect_boxes(&a, &a).is_none());
+    }
+
+    #[test]
+    fn render_into_skips_cells_outside_buffer() {
+        let mut buf = vec![vec![' '; 3]; 2];
+        segment(&[(1, 1, 'a'), (3, 2, 'b'), (4, 1, 'c'), (1, 3, 'd')]).render_into(&mut buf);
+        assert_eq!(buf, vec![vec!['a', ' ', ' '], vec![' ', ' ', 'b']]);
+    }
+
+    #[test]
+    fn trimmed_keeps_last_visible_cells() {
+        let drawing = segment(&[(1, 1, 'a'), (2, 1, 'b'), (1, 1, ' '), (2, 1, 'c')]);
+        assert_eq!(cells(&drawing.trimmed()), vec![(2, 1, 'c')]);
+    }
+
+    #[test]
+    fn leading_tab_expands_to_next_stop() {
+        let text = cells(&Segment::from_str(Point::new(1, 1), "\tx", Default::default()));
+        assert_eq!(text.last(), Some(&(5, 1, 'x')));
+        assert!(text[..4].iter().all(|cell| cell.2 == ' '));
+
+        let text =
+            Segment::from_str_with_tab_width(Point::new(1, 1), "ab\tx", Default::default(), 8);
+        assert_eq!(cells(&text).last(), Some(&(9, 1, 'x')));
+    }
+
+    #[test]
+    fn text_width_follows_tab_width() {
+        assert_eq!(text_width("\tx"), 5);
+        assert_eq!(text_width_with_tab_width("ab\tx", 8), 9);
+        assert_eq!(text_width_with_tab_width("ab\tx", 0), 4);
+    }
+
+    #[test]
+    fn text_width_is_widest_line() {
+        assert_eq!(text_width("ab\r\nwxyz\nc"), 4);
+        assert_eq!(text_width("ab\r\n"), 2);
+    }
+
+    #[test]
+    fn polygon_passes_through_its_vertices() {
+        let diamond = cells(&Tracer::default().polygon(Point::new(10, 10), 2, 4).unwrap());
+        for (x, y) in &[(10, 8), (14, 10), (10, 12), (6, 10)] {
+            assert!(diamond.iter().any(|cell| (cell.0, cell.1) == (*x, *y)));
+        }
+        assert!(Tracer::default().polygon(Point::new(10, 10), 2, 2).is_none());
+    }
+
+    #[test]
+    fn diff_snapshots_reports_visible_changes() {
+        let old = [segment(&[(1, 1, 'a'), (2, 1, 'b')])];
+        let new = [segment(&[(1, 1, 'a')]), segment(&[(2, 1, 'c'), (3, 1, ' ')])];
+        let (added, removed) = diff_snapshots(&old, &new);
+        assert_eq!(added, vec![Cell::new(Point::new(2, 1), 'c')]);
+        assert_eq!(removed, vec![Cell::new(Point::new(2, 1), 'b')]);
+    }
+
+    #[test]
+    fn spacing_draws_every_nth_step() {
+        let dotted = Tracer::default().with_spacing(3).trace(Point::new(1, 1), Point::new(10, 1));
+        let xs: Vec<u16> = cells(&dotted).iter().map(|cell| cell.0).collect();
+        assert_eq!(xs, vec![4, 7, 10]);
+    }
+
+    #[test]
+    fn within_polygon_keeps_enclosed_cells() {
+        let triangle = [Point::new(1, 1), Point::new(9, 1), Point::new(1, 9)];
+        let drawing = segment(&[(2, 2, 'a'), (8, 8, 'b'), (1, 5, 'c')]);
+        assert_eq!(cells(&drawing.within_polygon(&triangle)), vec![(2, 2, 'a'), (1, 5, 'c')]);
+    }
+
+    #[test]
+    fn checker_fill_alternates_glyphs() {
+        let (a, b) = (Point::new(1, 1), Point::new(4, 4));
+        let fill = cells(&Segment::filled_rect(a, b, FillPattern::Checker('#', '.')));
+        assert_eq!(fill.len(), 16);
+        assert_eq!(fill.iter().filter(|cell| cell.2 == '#').count(), 8);
+        assert_eq!(fill[..2], [(1, 1, '#'), (2, 1, '.')]);
+    }
+
+    #[test]
+    fn compact_round_trips_cells() {
+        let drawing = segment(&[
+            (1, 1, '_'),
+            (2, 1, '_'),
+            (3, 1, '_'),
+            (4, 2, '\\'),
+            (5, 3, '\\'),
+            (9, 9, 'x'),
+        ]);
+        let compact = drawing.compact();
+        assert_eq!(compact.runs.len(), 3);
+        assert_eq!(cells(&compact.expand()), cells(&drawing));
+    }
+
+    #[test]
+    fn index_round_trips_points() {
+        assert_eq!(Point::new(1, 1).to_index(10), 0);
+        assert_eq!(Point::new(3, 2).to_index(10), 12);
+        for index in 0..30 {
+            assert_eq!(Point::from_index(index, 10).to_index(10), index);
+        }
+        assert_eq!(Point::from_index(5, 0), Point::default());
+    }
+
+    #[test]
+    fn jitter_is_reproducible() {
+        let (from, to) = (Point::new(1, 5), Point::new(30, 5));
+        let trace = |seed| cells(&Tracer::default().with_jitter(seed, 1).trace(from, to));
+        assert_eq!(trace(7), trace(7));
+        assert!(trace(7).iter().all(|cell| (4..=6).contains(&cell.1)));
+
+        let steady = Tracer::default().with_jitter(7, 0).trace(from, to);
+        assert_eq!(cells(&steady), cells(&Tracer::default().trace(from, to)));
+    }
+
+    #[test]
+    fn grid_joins_lines_with_box_glyphs() {
+        let table = cells(&Segment::grid(Point::new(1, 1), 1, 1, 2, 2).unwrap());
+        let glyph_at =
+            |x, y| table.iter().find(|cell| (cell.0, cell.1) == (x, y)).map(|cell| cell.2);
+        assert_eq!(table.len(), 21);
+        assert_eq!(glyph_at(1, 1), Some('┌'));
+        assert_eq!(glyph_at(3, 3), Some('┼'));
+        assert_eq!(glyph_at(5, 5), Some('┘'));
+        assert_eq!(glyph_at(2, 2), None);
+        assert!(Segment::grid(Point::new(1, 1), 1, 1, u16::MAX, 1).is_none());
+    }
+
+    #[test]
+    fn duplicates_are_reported_once() {
+        let drawing = segment(&[(1, 1, 'a'), (2, 1, 'b'), (1, 1, 'c'), (1, 1, 'd')]);
+        assert_eq!(drawing.duplicates(), vec![Point::new(1, 1)]);
+    }
+
+    #[test]
+    fn route_goes_around_obstacles() {
+        let area = (Point::new(1, 1), Point::new(10, 10));
+        let (from, to) = (Point::new(2, 2), Point::new(8, 2));
+        let wall: HashSet<Point> = (1..=4).map(|y| Point::new(5, y)).collect();
+        let route = cells(&Tracer::default().route(from, to, &wall, area).unwrap());
+        assert_eq!(route.first().map(|cell| (cell.0, cell.1)), Some((2, 2)));
+        assert_eq!(route.last().map(|cell| (cell.0, cell.1)), Some((8, 2)));
+        assert!(route.iter().all(|cell| !wall.contains(&Point::new(cell.0, cell.1))));
+
+        let wall: HashSet<Point> = (1..=10).map(|y| Point::new(5, y)).collect();
+        assert!(Tracer::default().route(from, to, &wall, area).is_none());
+    }
+
+    #[test]
+    fn horizontal_run_moves_cursor_once() {
+        let run = segment(&[(3, 2, 'a'), (4, 2, 'b'), (5, 2, 'c')]);
+        let format = style::Format::default();
+        assert_eq!(run.to_string(), format!("{}{}abc{}", format, Point::new(3, 2), format));
+    }
+
+    #[test]
+    fn dashed_rectangle_keeps_corners() {
+        let (a, b) = (Point::new(1, 1), Point::new(6, 4));
+        let dashed = cells(&Tracer::default().with_spacing(3).rectangle(a, b));
+        for (x, y) in &[(1, 1), (6, 1), (6, 4), (1, 4)] {
+            assert!(dashed.iter().any(|cell| (cell.0, cell.1) == (*x, *y)));
+        }
+        assert!(dashed.len() < cells(&Tracer::default().rectangle(a, b)).len());
+    }
+
+    #[test]
+    fn diagonal_path_is_longer_than_its_cells() {
+        let diagonal = segment(&[(1, 1, '\\'), (2, 2, '\\'), (3, 3, '\\'), (4, 4, '\\')]);
+        assert!((diagonal.path_length() - 3.0 * 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(segment(&[(1, 1, '.')]).path_length(), 0.0);
+    }
+
+    #[test]
+    fn from_str_starts_lines_below_each_other() {
+        let text = Segment::from_str(Point::new(3, 2), "ab\nc", Default::default());
+        assert_eq!(cells(&text), vec![(3, 2, 'a'), (4, 2, 'b'), (3, 3, 'c')]);
+    }
+
+    #[test]
+    fn path_modes_step_differently() {
+        let points = |mode| {
+            let tracer = Tracer::default().with_path_mode(mode);
+            let trace = cells(&tracer.trace(Point::new(1, 1), Point::new(4, 2)));
+            trace.iter().map(|cell| (cell.0, cell.1)).collect::<Vec<_>>()
+        };
+        assert_eq!(points(PathMode::Diagonal), vec![(2, 2), (3, 2), (4, 2)]);
+        assert_eq!(points(PathMode::AxisFirst), vec![(2, 1), (3, 1), (4, 1), (4, 2)]);
+        assert_eq!(points(PathMode::DominantFirst), vec![(2, 1), (3, 1), (4, 2)]);
+    }
+
+    #[test]
+    fn clamped_to_drops_cells_out_of_bounds() {
+        let drawing = segment(&[(1, 1, 'a'), (5, 2, 'b'), (3, 9, 'c')]);
+        assert!(!drawing.in_bounds(5, 5));
+        let clamped = drawing.clamped_to(5, 5);
+        assert_eq!(cells(&clamped), vec![(1, 1, 'a'), (5, 2, 'b')]);
+        assert!(clamped.in_bounds(5, 5));
+    }
+
+    #[test]
+    fn from_points_keeps_order() {
+        let built = Segment::from_points(vec![(Point::new(1, 2), 'a'), (Point::new(3, 4), 'b')]);
+        assert_eq!(cells(&built), vec![(1, 2, 'a'), (3, 4, 'b')]);
+    }
 }
@@ -10,7 +10,7 @@ pub static RESET_FORMAT: Format = Format {
     fg_color: Color::Reset,
 };
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct Format {
     pub bg_color: Color,
     pub fg_color: Color,
@@ -10,20 +10,28 @@ use crossterm::terminal;
 use crossterm::tty::IsTty;
 use crossterm::{ErrorKind, ExecutableCommand};
 
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
 type SomeResult<T = ()> = result::Result<T, Box<dyn error::Error>>;
-type ExecResult<'a> = SomeResult<&'a mut Terminal>;
+type ExecResult<'a, W> = SomeResult<&'a mut Terminal<W>>;
 
 pub fn is_tty() -> bool {
     io::stdout().is_tty() && io::stdin().is_tty()
 }
 
-pub struct Terminal {
-    stdout: io::Stdout,
+/// Sets up and restores the terminal, writing its escape sequences to `out`, which is normally
+/// stdout.
+pub struct Terminal<W: io::Write = io::Stdout> {
+    out: W,
 }
 
-impl Terminal {
-    pub fn new(stdout: io::Stdout) -> Self {
-        Self { stdout }
+impl<W: io::Write> Terminal<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
     }
 
     pub fn read_event(&self) -> Result<Option<Event>, InputError> {
@@ -34,52 +42,70 @@ impl Terminal {
         Ok(Some(event::read()?.try_into()?))
     }
 
-    pub fn enable_raw_mode(&mut self) -> ExecResult {
+    pub fn enable_raw_mode(&mut self) -> ExecResult<'_, W> {
         terminal::enable_raw_mode()?;
         Ok(self)
     }
 
-    pub fn disable_raw_mode(&mut self) -> ExecResult {
+    pub fn disable_raw_mode(&mut self) -> ExecResult<'_, W> {
         terminal::disable_raw_mode()?;
         Ok(self)
     }
 
-    pub fn hide_cursor(&mut self) -> ExecResult {
-        self.stdout.execute(crossterm::cursor::Hide)?;
+    pub fn hide_cursor(&mut self) -> ExecResult<'_, W> {
+        self.out.execute(crossterm::cursor::Hide)?;
         Ok(self)
     }
 
-    pub fn show_cursor(&mut self) -> ExecResult {
-        self.stdout.execute(crossterm::cursor::Show)?;
+    pub fn show_cursor(&mut self) -> ExecResult<'_, W> {
+        self.out.execute(crossterm::cursor::Show)?;
         Ok(self)
     }
 
-    pub fn enter_alt_screen(&mut self) -> ExecResult {
-        self.stdout.execute(terminal::EnterAlternateScreen)?;
+    pub fn enter_alt_screen(&mut self) -> ExecResult<'_, W> {
+        self.out.execute(terminal::EnterAlternateScreen)?;
         Ok(self)
     }
 
-    pub fn leave_alt_screen(&mut self) -> ExecResult {
-        self.stdout.execute(terminal::LeaveAlternateScreen)?;
+    pub fn leave_alt_screen(&mut self) -> ExecResult<'_, W> {
+        self.out.execute(terminal::LeaveAlternateScreen)?;
         Ok(self)
     }
 
-    pub fn enable_mouse_capture(&mut self) -> ExecResult {
-        self.stdout.execute(event::EnableMouseCapture)?;
+    pub fn enable_mouse_capture(&mut self) -> ExecResult<'_, W> {
+        self.out.execute(event::EnableMouseCapture)?;
         Ok(self)
     }
 
-    pub fn disable_mouse_capture(&mut self) -> ExecResult {
-        self.stdout.execute(event::DisableMouseCapture)?;
+    pub fn disable_mouse_capture(&mut self) -> ExecResult<'_, W> {
+        self.out.execute(event::DisableMouseCapture)?;
         Ok(self)
     }
 
-    pub fn clear(&mut self) -> ExecResult {
-        self.stdout.execute(terminal::Clear(terminal::ClearType::All))?;
+    /// Returns the terminal's (columns, rows), or a standard 80x24 if it can't be determined.
+    pub fn size(&self) -> (u16, u16) {
+        match terminal::size() {
+            Ok((width, height)) if width > 0 && height > 0 => (width, height),
+            _ => DEFAULT_SIZE,
+        }
+    }
+
+    pub fn clear(&mut self) -> ExecResult<'_, W> {
+        self.out.execute(terminal::Clear(terminal::ClearType::All))?;
         Ok(self)
     }
 }
 
+impl<W: io::Write> io::Write for Terminal<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
 impl Default for Terminal {
     fn default() -> Self {
         Self::new(io::stdout())
@@ -177,7 +203,10 @@ impl TryFrom<event::MouseEvent> for MouseEvent {
     type Error = InputError;
 
     fn try_from(event: event::MouseEvent) -> Result<Self, Self::Error> {
-        let mouse = |x, y, action| Ok(MouseEvent::new((x, y), action));
+        // Terminal coordinates are 0-based, whereas grid points start at (1, 1)
+        let mouse = |x: u16, y: u16, action| {
+            Ok(MouseEvent::new((x.saturating_add(1), y.saturating_add(1)), action))
+        };
         match event {
             event::MouseEvent::Down(_, x, y, _) => mouse(x, y, MouseAction::Press),
             event::MouseEvent::Up(_, x, y, _) => mouse(x, y, MouseAction::Release),
@@ -6,13 +6,135 @@ use crate::canvas;
 use crate::export;
 use crate::menu;
 
+const TOOLBAR_HEIGHT: u16 = 3;
+
+// Rows beyond the terminal height are clamped to the last one
+const STATUS_POS: (u16, u16) = (1, 300);
+
+#[rustfmt::skip]
+static INFO_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::Black,
+    fg_color: terminal::Color::Green,
+};
+
+#[rustfmt::skip]
+static MARKER_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::Reset,
+    fg_color: terminal::Color::Yellow,
+};
+
+#[rustfmt::skip]
+static ERROR_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::Black,
+    fg_color: terminal::Color::Red,
+};
+
+/// What the terminal shows once the app exits.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum OnExit {
+    /// Wipes the drawing before returning to the original screen.
+    Clear,
+    /// Returns to the original screen and prints the drawing there, leaving it in view and in
+    /// the scrollback.
+    Leave,
+    /// Returns to the original screen, only restoring the cursor and input modes.
+    #[default]
+    ShowCursorOnly,
+}
+
 pub struct Opts {
     char_set: grid::CharSet,
+    caption: Option<String>,
+    speed_glyphs: Vec<char>,
+    straighten_threshold: Option<f64>,
+    nib_angle: Option<f64>,
+    cursor_marker: Option<char>,
+    theme: canvas::Theme,
+    cell_cap: Option<usize>,
+    on_exit: OnExit,
+    geometry_snap: bool,
+    toolbar_behavior: canvas::ToolbarBehavior,
+    line_chaining: bool,
+    graph_spacing: Option<u16>,
 }
 
 impl Opts {
     pub fn new(char_set: grid::CharSet) -> Self {
-        Self { char_set }
+        Self {
+            char_set,
+            caption: None,
+            speed_glyphs: Vec::new(),
+            straighten_threshold: None,
+            nib_angle: None,
+            cursor_marker: None,
+            theme: canvas::Theme::default(),
+            cell_cap: None,
+            on_exit: OnExit::default(),
+            geometry_snap: false,
+            toolbar_behavior: canvas::ToolbarBehavior::default(),
+            line_chaining: false,
+            graph_spacing: None,
+        }
+    }
+
+    pub fn with_graph_spacing(mut self, spacing: Option<u16>) -> Self {
+        self.graph_spacing = spacing;
+        self
+    }
+
+    pub fn with_line_chaining(mut self, enabled: bool) -> Self {
+        self.line_chaining = enabled;
+        self
+    }
+
+    pub fn with_toolbar_behavior(mut self, behavior: canvas::ToolbarBehavior) -> Self {
+        self.toolbar_behavior = behavior;
+        self
+    }
+
+    pub fn with_geometry_snap(mut self, enabled: bool) -> Self {
+        self.geometry_snap = enabled;
+        self
+    }
+
+    pub fn with_on_exit(mut self, on_exit: OnExit) -> Self {
+        self.on_exit = on_exit;
+        self
+    }
+
+    pub fn with_cell_cap(mut self, cap: Option<usize>) -> Self {
+        self.cell_cap = cap;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: canvas::Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    pub fn with_cursor_marker(mut self, marker: Option<char>) -> Self {
+        self.cursor_marker = marker;
+        self
+    }
+
+    pub fn with_straighten_threshold(mut self, threshold: Option<f64>) -> Self {
+        self.straighten_threshold = threshold;
+        self
+    }
+
+    pub fn with_nib_angle(mut self, degrees: Option<f64>) -> Self {
+        self.nib_angle = degrees;
+        self
+    }
+
+    pub fn with_speed_glyphs(mut self, speed_glyphs: Vec<char>) -> Self {
+        self.speed_glyphs = speed_glyphs;
+        self
+    }
+
+    pub fn with_caption(mut self, caption: Option<String>) -> Self {
+        self.caption = caption;
+        self
     }
 }
 
@@ -30,34 +152,109 @@ pub fn launch(opts: Opts) -> crate::Result {
         .hide_cursor()?
         .clear()?;
 
-    let result = run_canvas(&mut terminal, opts.char_set);
+    let on_exit = opts.on_exit;
+    let mut canvas = canvas::Canvas::auto(|| terminal.size(), TOOLBAR_HEIGHT);
+    let result = run_canvas(&mut terminal, &mut canvas, opts);
+    let drawing = match result {
+        Ok(()) if on_exit == OnExit::Leave => Some(canvas.export(&export::Text)),
+        _ => None,
+    };
+    let restored = restore(&mut terminal, on_exit, drawing.as_deref());
+    result.and(restored)
+}
 
-    terminal
-        .clear()?
-        .show_cursor()?
-        .disable_mouse_capture()?
-        .disable_raw_mode()?
-        .leave_alt_screen()?;
+/// Undoes the terminal setup. Every step is attempted even if an earlier one fails, so that the
+/// terminal isn't left half restored, and the first error is returned. When leaving the drawing
+/// in view, it's printed once back on the original screen, if everything before succeeded.
+fn restore<W: Write>(
+    terminal: &mut terminal::Terminal<W>,
+    on_exit: OnExit,
+    drawing: Option<&str>,
+) -> crate::Result {
+    let cleared = match on_exit {
+        OnExit::Clear => terminal.clear().map(|_| ()),
+        _ => Ok(()),
+    };
+    let shown = terminal.show_cursor().map(|_| ());
+    let released = terminal.disable_mouse_capture().map(|_| ());
+    let cooked = terminal.disable_raw_mode().map(|_| ());
+    let left = terminal.leave_alt_screen().map(|_| ());
+    let restored = cleared.and(shown).and(released).and(cooked).and(left);
 
-    result
+    match drawing {
+        Some(drawing) if on_exit == OnExit::Leave && restored.is_ok() => {
+            writeln!(terminal, "{}", drawing)?;
+            terminal.flush()?;
+            Ok(())
+        }
+        _ => restored,
+    }
 }
 
-fn run_canvas(terminal: &mut terminal::Terminal, char_set: grid::CharSet) -> crate::Result {
+fn run_canvas(
+    terminal: &mut terminal::Terminal,
+    canvas: &mut canvas::Canvas,
+    opts: Opts,
+) -> crate::Result {
     let mut screen = io::stdout();
-    let mut canvas = canvas::Canvas::new();
-    let mut sketch = grid::Segment::new();
+    let mut anchor = grid::Point::default();
     let mut toolbar = menu::ToolBar::new();
-    let mut tool = canvas::Tool::default();
     let mut file_name: Option<String> = None;
-    let mut file_name_print = grid::Segment::new();
+    let mut status = grid::Segment::new();
+    let mut marker = grid::Segment::new();
+    // Action awaiting a character argument
+    let mut pending: Option<char> = None;
+    // Character to replace, once given to the replace action
+    let mut replaced: Option<char> = None;
+
+    if let Some(ref caption) = opts.caption {
+        toolbar.set_caption(caption);
+    }
+    canvas.set_caption(opts.caption);
+    canvas.set_speed_glyphs(opts.speed_glyphs);
+    canvas.set_auto_straighten(opts.straighten_threshold);
+    canvas.set_nib_angle(opts.nib_angle);
+    canvas.set_theme(opts.theme);
+    canvas.set_cell_cap(opts.cell_cap);
+    canvas.set_geometry_snap(opts.geometry_snap);
+    canvas.set_toolbar_behavior(opts.toolbar_behavior);
+    canvas.set_line_chaining(opts.line_chaining);
+    canvas.set_graph_mode(opts.graph_spacing);
+    canvas.set_cursor_marker(opts.cursor_marker.map(|glyph| (glyph, MARKER_FORMAT)));
 
-    let tracer = grid::Tracer::new(char_set);
+    let tracer = grid::Tracer::new(opts.char_set);
 
     loop {
         match terminal.read_event() {
             Ok(event) => {
+                canvas.fit_to(|| terminal.size());
                 if let Some(event) = event {
                     match event {
+                        terminal::Event::Key(terminal::KeyEvent { char, .. })
+                            if pending.is_some() =>
+                        {
+                            set_status(&mut screen, &mut status, "", INFO_FORMAT)?;
+                            let result = match pending.take() {
+                                Some('x') => canvas.erase_glyph(char),
+                                Some('p') => match replaced.take() {
+                                    Some(from) => canvas.replace_glyph(from, char),
+                                    None => {
+                                        replaced = Some(char);
+                                        pending = Some('p');
+                                        let prompt =
+                                            format!("Replace '{}' with which character?", char);
+                                        set_status(&mut screen, &mut status, &prompt, INFO_FORMAT)?;
+                                        Ok(())
+                                    }
+                                },
+                                _ => Ok(()),
+                            };
+
+                            if let Err(error) = result {
+                                let message = error.to_string();
+                                set_status(&mut screen, &mut status, &message, ERROR_FORMAT)?;
+                            }
+                        }
                         terminal::Event::Key(terminal::KeyEvent { char, modifier }) => {
                             match (char, modifier) {
                                 ('q', _) => break,
@@ -66,64 +263,284 @@ fn run_canvas(terminal: &mut terminal::Terminal, char_set: grid::CharSet) -> cra
                                         screen.erase(&mut segment)?;
                                     }
                                 }
+                                ('r', Some(terminal::KeyModifier::Ctrl)) => {
+                                    if let Some(mut segment) = canvas.redo() {
+                                        screen.erase(&mut segment)?;
+                                    }
+                                }
                                 ('k', _) => {
                                     canvas.clear();
-                                    sketch.clear();
                                     terminal.clear()?;
                                 }
+                                ('g', _) | ('G', _) => {
+                                    let (orientation, position) = match char {
+                                        'g' => (canvas::Orientation::Horizontal, canvas.cursor.y),
+                                        _ => (canvas::Orientation::Vertical, canvas.cursor.x),
+                                    };
+
+                                    match canvas.remove_guide(orientation, position) {
+                                        Some(guide) => {
+                                            let (start, end) = canvas.bounds();
+                                            let segment = guide.segment(start, end);
+                                            screen.erase(&mut canvas.to_screen(&segment))?;
+                                        }
+                                        None => canvas.add_guide(orientation, position),
+                                    }
+                                }
+                                ('l', None) => {
+                                    if let Some(index) = canvas.strokes().len().checked_sub(1) {
+                                        let locked = !canvas.is_locked(index);
+                                        canvas.set_locked(index, locked)?;
+
+                                        let message = if locked {
+                                            "Stroke locked"
+                                        } else {
+                                            "Stroke unlocked"
+                                        };
+                                        set_status(&mut screen, &mut status, message, INFO_FORMAT)?;
+                                    }
+                                }
+                                ('l', Some(terminal::KeyModifier::Ctrl)) => {
+                                    // The toolbar and status are rewritten below
+                                    canvas.refresh(&mut screen)?;
+                                }
+                                ('c', Some(terminal::KeyModifier::Ctrl)) => {
+                                    let content = canvas.export(&export::Text);
+                                    export::to_clipboard(&mut screen, &content)?;
+                                    // Whether the terminal took it can't be known
+                                    let message = "Sent to the clipboard, if the terminal allows";
+                                    set_status(&mut screen, &mut status, message, INFO_FORMAT)?;
+                                }
                                 ('s', Some(terminal::KeyModifier::Ctrl)) => {
-                                    let blueprint: grid::Segment = canvas.snapshot().iter().sum();
+                                    let content = canvas.export(&export::Text);
                                     match file_name {
-                                        Some(ref name) => export::to_file_as(blueprint, name)?,
+                                        Some(ref name) => export::to_file_as(&content, name)?,
                                         None => {
-                                            let name = export::to_file(blueprint)?;
-                                            file_name_print = grid::Segment::from_str(
-                                                (1, 300).into(),
+                                            let name = export::to_file(&content)?;
+                                            set_status(
+                                                &mut screen,
+                                                &mut status,
                                                 &name,
-                                                terminal::Format::new(
-                                                    terminal::Color::Black,
-                                                    terminal::Color::Green,
-                                                ),
-                                            );
-
+                                                INFO_FORMAT,
+                                            )?;
                                             file_name = Some(name);
                                         }
                                     }
                                 }
-                                (n, _) if n.is_digit(10) => {
-                                    tool = match n {
+                                ('r', None) => {
+                                    // Tile the last stroke to the right of itself
+                                    let width = canvas
+                                        .strokes()
+                                        .last()
+                                        .and_then(|segment| segment.boundaries())
+                                        .map_or(0, |(start, end)| end.x - start.x + 1);
+
+                                    if let Err(error) = canvas.repeat_last(width.into(), 0) {
+                                        let message = error.to_string();
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            ERROR_FORMAT,
+                                        )?;
+                                    }
+                                }
+                                ('s', None) => match canvas.shapify_last(&tracer) {
+                                    Ok(Some(mut freehand)) => screen.erase(&mut freehand)?,
+                                    Ok(None) => {
+                                        let message = "Stroke doesn't resemble a shape";
+                                        set_status(&mut screen, &mut status, message, INFO_FORMAT)?;
+                                    }
+                                    Err(error) => {
+                                        let message = error.to_string();
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            ERROR_FORMAT,
+                                        )?;
+                                    }
+                                },
+                                ('b', _) => match canvas.undo_cells(1) {
+                                    Ok(mut removed) => screen.erase(&mut removed)?,
+                                    Err(error) => {
+                                        let message = error.to_string();
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            ERROR_FORMAT,
+                                        )?;
+                                    }
+                                },
+                                ('R', _) => match canvas.rotate_all() {
+                                    // Everything is redrawn below in its new place
+                                    Ok(()) => {
+                                        terminal.clear()?;
+                                    }
+                                    Err(error) => {
+                                        let message = error.to_string();
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            ERROR_FORMAT,
+                                        )?;
+                                    }
+                                },
+                                ('p', _) => {
+                                    pending = Some(char);
+                                    let prompt = "Replace which character?";
+                                    set_status(&mut screen, &mut status, prompt, INFO_FORMAT)?;
+                                }
+                                ('x', _) => {
+                                    pending = Some(char);
+                                    let prompt = "Erase which character?";
+                                    set_status(&mut screen, &mut status, prompt, INFO_FORMAT)?;
+                                }
+                                ('i', _) => {
+                                    let stats = canvas.stats().to_string();
+                                    set_status(&mut screen, &mut status, &stats, INFO_FORMAT)?;
+                                }
+                                ('o', _) => match canvas.clear_outline() {
+                                    Some(mut outline) => screen.erase(&mut outline)?,
+                                    None => {
+                                        let index = canvas.strokes().len().saturating_sub(1);
+                                        if let Err(error) = canvas.outline_segment(&tracer, index) {
+                                            let message = error.to_string();
+                                            set_status(
+                                                &mut screen,
+                                                &mut status,
+                                                &message,
+                                                ERROR_FORMAT,
+                                            )?;
+                                        }
+                                    }
+                                },
+                                ('m', _) => {
+                                    // Mirror the last stroke across its rightmost column
+                                    let axis = canvas
+                                        .strokes()
+                                        .last()
+                                        .and_then(|segment| segment.boundaries())
+                                        .map_or(0, |(_, end)| end.x);
+
+                                    if let Err(error) = canvas.duplicate_mirrored(axis) {
+                                        let message = error.to_string();
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            ERROR_FORMAT,
+                                        )?;
+                                    }
+                                }
+                                (n, _) if n.is_ascii_digit() => {
+                                    canvas.set_tool(match n {
+                                        '4' => canvas::Tool::Measure,
                                         '3' => canvas::Tool::Erase,
                                         '2' => canvas::Tool::Line,
                                         _ => canvas::Tool::Plot,
-                                    };
+                                    });
 
-                                    toolbar.highlight_tool(tool);
+                                    toolbar.highlight_tool(canvas.tool());
+                                }
+                                ('t', _) => {
+                                    toolbar.highlight_tool(canvas.cycle_tool());
                                 }
                                 _ => {}
                             }
                         }
-                        // Reserve toolbar space
-                        terminal::Event::Mouse(event) if event.pos.1 > 3 => {
-                            match (event.action, event.pos) {
+                        // Reserve toolbar space, only following drags out of the canvas when
+                        // they are clamped to it
+                        terminal::Event::Mouse(mut event)
+                            if canvas.contains(canvas.canvas_point(event.pos.into()))
+                                || (!matches!(event.action, terminal::MouseAction::Press)
+                                    && canvas.toolbar_behavior()
+                                        == canvas::ToolbarBehavior::Clamp) =>
+                        {
+                            let grid::Point { x, y } =
+                                canvas.clamp(canvas.canvas_point(event.pos.into()));
+                            event.pos = (x, y);
+
+                            let pos = match canvas.tool() {
+                                canvas::Tool::Erase => event.pos,
+                                _ => {
+                                    let grid::Point { x, y } = canvas.snap(event.pos.into());
+                                    if canvas.geometry_snap((x, y).into()).is_some() {
+                                        let message = format!("Snapped to {}, {}", x, y);
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            INFO_FORMAT,
+                                        )?;
+                                    }
+                                    (x, y)
+                                }
+                            };
+
+                            match (event.action, pos) {
                                 (terminal::MouseAction::Press, (x, y)) => {
-                                    canvas.cursor.move_to(x, y)
+                                    canvas.cursor = canvas.line_start((x, y).into());
+                                    anchor = canvas.cursor;
                                 }
-                                (terminal::MouseAction::Drag, (x, y)) => match tool {
+                                (terminal::MouseAction::Drag, (x, y)) => match canvas.tool() {
                                     canvas::Tool::Plot => {
-                                        sketch += tracer.trace(canvas.cursor, (x, y).into());
+                                        let mut stroke = tracer.trace(canvas.cursor, (x, y).into());
+                                        if let Some(glyph) =
+                                            canvas.speed_glyph(canvas.cursor, (x, y).into())
+                                        {
+                                            stroke.fill(glyph);
+                                        }
+                                        let stroke =
+                                            canvas.nibbed(stroke, canvas.cursor, (x, y).into());
+                                        canvas.sketch_extend(stroke);
                                         canvas.cursor.move_to(x, y);
                                     }
                                     canvas::Tool::Line => {
-                                        screen.erase(&mut sketch)?;
-                                        sketch = tracer.trace(canvas.cursor, (x, y).into());
+                                        let mut previous =
+                                            canvas.preview_line(&tracer, (x, y).into());
+                                        screen.erase(&mut previous)?;
                                     }
                                     canvas::Tool::Erase => {
-                                        sketch.add(grid::Cell::new((x, y).into(), ' '));
+                                        canvas.sketch_add(grid::Cell::new((x, y).into(), ' '));
+                                    }
+                                    canvas::Tool::Measure => {
+                                        let measurement =
+                                            canvas::Measurement::new(anchor, (x, y).into());
+                                        let message = measurement.to_string();
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            INFO_FORMAT,
+                                        )?;
                                     }
                                 },
                                 (terminal::MouseAction::Release, _) => {
-                                    canvas.add(sketch.clone());
-                                    sketch.clear();
+                                    if canvas.tool() == canvas::Tool::Measure {
+                                        set_status(&mut screen, &mut status, "", INFO_FORMAT)?;
+                                    }
+                                    if canvas.tool() == canvas::Tool::Plot {
+                                        if let Some(mut freehand) =
+                                            canvas.straighten_sketch(&tracer, anchor)
+                                        {
+                                            screen.erase(&mut freehand)?;
+                                        }
+                                    }
+                                    if let Err(error) = canvas.commit_sketch() {
+                                        screen.erase(
+                                            &mut canvas.replace_sketch(grid::Segment::new()),
+                                        )?;
+                                        let message = error.to_string();
+                                        set_status(
+                                            &mut screen,
+                                            &mut status,
+                                            &message,
+                                            ERROR_FORMAT,
+                                        )?;
+                                    }
                                 }
                             }
                         }
@@ -131,7 +548,13 @@ fn run_canvas(terminal: &mut terminal::Terminal, char_set: grid::CharSet) -> cra
                     }
                 }
 
-                write!(screen, "{}{}{}{}", canvas, sketch, toolbar, file_name_print)?;
+                // The marker may have moved, and anything it covered is redrawn below
+                screen.erase(&mut marker)?;
+
+                write!(screen, "{}{}{}", canvas.to_ansi(), toolbar, status)?;
+                if let Some(cursor_marker) = canvas.cursor_marker() {
+                    marker = cursor_marker;
+                }
                 screen.flush()?;
             }
             Err(terminal::InputError::UnknownError(error)) => return Err(error.into()),
@@ -141,3 +564,63 @@ fn run_canvas(terminal: &mut terminal::Terminal, char_set: grid::CharSet) -> cra
 
     Ok(())
 }
+
+fn set_status<W: Write>(
+    screen: &mut W,
+    status: &mut grid::Segment,
+    text: &str,
+    format: terminal::Format,
+) -> io::Result<()> {
+    screen.erase(status)?;
+    *status = grid::Segment::from_str(STATUS_POS.into(), text, format);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLEAR: &str = "\x1b[2J";
+    const SHOW_CURSOR: &str = "\x1b[?25h";
+    const LEAVE_ALT_SCREEN: &str = "\x1b[?1049l";
+
+    fn restored(on_exit: OnExit, drawing: Option<&str>) -> String {
+        let mut terminal = terminal::Terminal::new(Vec::new());
+        restore(&mut terminal, on_exit, drawing).unwrap();
+        String::from_utf8(terminal.get_ref().clone()).unwrap()
+    }
+
+    #[test]
+    fn clear_wipes_before_leaving_alt_screen() {
+        let out = restored(OnExit::Clear, None);
+        assert!(out.starts_with(CLEAR));
+        assert!(out.contains(SHOW_CURSOR));
+        assert!(out.ends_with(LEAVE_ALT_SCREEN));
+    }
+
+    #[test]
+    fn show_cursor_only_leaves_screen_alone() {
+        let out = restored(OnExit::ShowCursorOnly, Some("*"));
+        assert!(!out.contains(CLEAR));
+        assert!(out.contains(SHOW_CURSOR));
+        assert!(out.ends_with(LEAVE_ALT_SCREEN));
+    }
+
+    #[test]
+    fn leave_prints_drawing_last() {
+        let out = restored(OnExit::Leave, Some("*\n"));
+        let (_, after) = out.split_at(out.find(LEAVE_ALT_SCREEN).unwrap());
+        assert_eq!(after, format!("{}*\n\n", LEAVE_ALT_SCREEN));
+        assert!(!after.contains(CLEAR));
+    }
+
+    #[test]
+    fn leave_keeps_drawing_on_main_screen() {
+        let out = restored(OnExit::Leave, Some("/\\\n"));
+        assert!(out.contains(SHOW_CURSOR));
+        assert!(!out.contains(CLEAR));
+
+        let drawing = out.find("/\\").unwrap();
+        assert!(out.find(LEAVE_ALT_SCREEN).is_some_and(|left| left < drawing));
+    }
+}
@@ -1,50 +1,2796 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::io::Write;
+use std::iter;
+use std::mem;
+use std::time;
 
 use terminal::grid;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+use crate::export;
+use crate::font;
+use crate::svg;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Tool {
+    #[default]
     Plot,
     Line,
     Erase,
+    Measure,
 }
 
-impl Default for Tool {
-    fn default() -> Self {
-        Tool::Plot
+impl Tool {
+    pub const ALL: [Tool; 4] = [Tool::Plot, Tool::Line, Tool::Erase, Tool::Measure];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|tool| *tool == self).unwrap_or_default();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+/// What happens to a drag leaving the canvas, such as into the toolbar.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum ToolbarBehavior {
+    /// Drag events outside of the canvas are dropped.
+    #[default]
+    Ignore,
+    /// The drag carries on along the nearest canvas edge.
+    Clamp,
+}
+
+#[rustfmt::skip]
+static OVERLAY_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::Reset,
+    fg_color: terminal::Color::DarkGrey,
+};
+
+#[rustfmt::skip]
+static ERASE_PREVIEW_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::DarkRed,
+    fg_color: terminal::Color::White,
+};
+
+#[rustfmt::skip]
+static LIGHT_PEN_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::White,
+    fg_color: terminal::Color::Black,
+};
+
+const GUIDE_SNAP_DISTANCE: u16 = 1;
+const GEOMETRY_SNAP_DISTANCE: u16 = 1;
+
+// Distance in cells a routed connector may stray beyond the box spanned by its ends
+const ROUTE_MARGIN: u16 = 10;
+
+// Distance in cells a freehand stroke may stray from the shape it's recognized as
+const SHAPE_TOLERANCE: u16 = 1;
+// Share of a rectangle's border a freehand stroke must pass by to be recognized as one
+const SHAPE_COVERAGE: f64 = 0.75;
+
+// Shades from an empty block of cells to a full one
+const DENSITY_RAMP: [char; 10] = [' ', '.', ':', '-', '=', '+', '*', '#', '%', '@'];
+
+// Number of cells a freehand stroke needs for auto straightening to consider it
+const STRAIGHTEN_MIN_LENGTH: usize = 3;
+
+// Number of cells a calligraphy nib covers when drawing across it
+const NIB_WIDTH: u16 = 3;
+
+// Distance in cells a stroke may be moved to keep it clear of the others when decluttering
+const DECLUTTER_MAX_SHIFT: i32 = 40;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Theme {
+    /// Returns the format of new strokes, including eraser strokes whose blanks paint the
+    /// background.
+    pub fn pen_format(self) -> terminal::Format {
+        match self {
+            Theme::Dark => terminal::RESET_FORMAT,
+            Theme::Light => LIGHT_PEN_FORMAT,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Guide {
+    pub orientation: Orientation,
+    pub position: u16,
+}
+
+impl Guide {
+    pub fn new(orientation: Orientation, position: u16) -> Self {
+        Self { orientation, position }
+    }
+
+    pub fn segment(&self, start: grid::Point, end: grid::Point) -> grid::Segment {
+        let mut segment = grid::Segment::new();
+        match self.orientation {
+            Orientation::Horizontal => (start.x..=end.x)
+                .for_each(|x| segment.add(grid::Cell::new((x, self.position).into(), '-'))),
+            Orientation::Vertical => (start.y..=end.y)
+                .for_each(|y| segment.add(grid::Cell::new((self.position, y).into(), ':'))),
+        }
+        segment.set_format(OVERLAY_FORMAT);
+        segment
+    }
+
+    fn snap(&self, point: grid::Point) -> Option<grid::Point> {
+        let (coordinate, snapped) = match self.orientation {
+            Orientation::Horizontal => (point.y, grid::Point::new(point.x, self.position)),
+            Orientation::Vertical => (point.x, grid::Point::new(self.position, point.y)),
+        };
+
+        let distance = cmp::max(coordinate, self.position) - cmp::min(coordinate, self.position);
+        if distance <= GUIDE_SNAP_DISTANCE {
+            Some(snapped)
+        } else {
+            None
+        }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StrokeOp {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, Clone)]
+pub struct StrokeDelta {
+    pub segment: grid::Segment,
+    pub op: StrokeOp,
+}
+
+impl StrokeDelta {
+    pub fn new(segment: grid::Segment, op: StrokeOp) -> Self {
+        Self { segment, op }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CanvasStats {
+    pub strokes: usize,
+    pub total_cells: usize,
+    pub bounds: Option<(grid::Point, grid::Point)>,
+    pub distinct_glyphs: usize,
+}
+
+impl fmt::Display for CanvasStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Strokes: {}, Cells: {}", self.strokes, self.total_cells)?;
+        if let Some((start, end)) = self.bounds {
+            write!(f, ", Size: {}x{}", end.x - start.x + 1, end.y - start.y + 1)?;
+        }
+        write!(f, ", Glyphs: {}", self.distinct_glyphs)
+    }
+}
+
+/// Distances and size spanned by a drag, as shown by the measure tool.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Measurement {
+    pub manhattan: u32,
+    pub euclidean: f64,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Measurement {
+    pub fn new(from: grid::Point, to: grid::Point) -> Self {
+        Self {
+            manhattan: from.manhattan_distance(to),
+            euclidean: from.distance(to),
+            width: from.x.abs_diff(to.x) + 1,
+            height: from.y.abs_diff(to.y) + 1,
+        }
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Manhattan: {}, Euclidean: {:.2}, Size: {}x{}",
+            self.manhattan, self.euclidean, self.width, self.height
+        )
+    }
+}
+
+/// A reversible change to the drawing, kept in the canvas history for undo and redo.
+#[derive(Debug, Clone)]
+pub enum Command {
+    AddStroke {
+        segment: grid::Segment,
+        locked: bool,
+    },
+    RemoveStroke {
+        index: usize,
+        segment: grid::Segment,
+        locked: bool,
+    },
+    ClearAll {
+        strokes: Vec<grid::Segment>,
+        locked: HashSet<usize>,
+    },
+    ReplaceStroke {
+        index: usize,
+        before: grid::Segment,
+        after: grid::Segment,
+    },
+    ReplaceAll {
+        before: Vec<grid::Segment>,
+        after: Vec<grid::Segment>,
+        locked_before: HashSet<usize>,
+        locked_after: HashSet<usize>,
+    },
+}
+
+pub type CommitListener = Box<dyn FnMut(&StrokeDelta)>;
+
+/// A line being drawn, as reported to the preview listener while dragging it out.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LinePreview {
+    pub from: grid::Point,
+    pub to: grid::Point,
+    /// Length of the path through the line's cells, longer than the cell count when diagonal.
+    pub length: f64,
+}
+
+pub type PreviewListener = Box<dyn FnMut(&LinePreview)>;
+
 pub struct Canvas {
     pub cursor: grid::Point,
+    origin: grid::Point,
+    bounds: (grid::Point, grid::Point),
+    reserved_rows: u16,
+    toolbar_behavior: ToolbarBehavior,
+    tool: Tool,
+    theme: Theme,
+    clip: Option<(grid::Point, grid::Point)>,
     design: Vec<grid::Segment>,
+    freehand_last: bool,
+    cell_cap: Option<usize>,
+    sketch: grid::Segment,
+    history: Vec<Command>,
+    redo_stack: Vec<Command>,
+    locked: HashSet<usize>,
+    outline: Option<grid::Segment>,
+    cursor_marker: Option<(char, terminal::Format)>,
+    guides: Vec<Guide>,
+    geometry_snap: bool,
+    graph_spacing: Option<u16>,
+    line_chaining: bool,
+    line_end: Option<grid::Point>,
+    speed_glyphs: Vec<char>,
+    nib_angle: Option<f64>,
+    straighten_threshold: Option<f64>,
+    caption: Option<String>,
+    render_substitutions: HashMap<char, char>,
+    tab_width: u16,
+    on_commit: Option<CommitListener>,
+    on_preview: Option<PreviewListener>,
 }
 
 impl Canvas {
     pub fn new() -> Self {
-        Self { design: Vec::new(), cursor: Default::default() }
+        Self {
+            clip: None,
+            design: Vec::new(),
+            freehand_last: false,
+            cell_cap: None,
+            sketch: grid::Segment::new(),
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            locked: HashSet::new(),
+            outline: None,
+            cursor_marker: None,
+            guides: Vec::new(),
+            geometry_snap: false,
+            graph_spacing: None,
+            line_chaining: false,
+            line_end: None,
+            speed_glyphs: Vec::new(),
+            nib_angle: None,
+            straighten_threshold: None,
+            cursor: Default::default(),
+            origin: Default::default(),
+            bounds: (Default::default(), grid::Point::new(80, 24)),
+            reserved_rows: 0,
+            toolbar_behavior: Default::default(),
+            tool: Default::default(),
+            theme: Default::default(),
+            caption: None,
+            render_substitutions: HashMap::new(),
+            tab_width: grid::DEFAULT_TAB_WIDTH,
+            on_commit: None,
+            on_preview: None,
+        }
     }
 
-    pub fn add(&mut self, segment: grid::Segment) {
-        self.design.push(segment)
+    /// Creates a canvas filling the screen, apart from the given number of rows at the top. `size`
+    /// reports the screen's (columns, rows), such as `Terminal::size`.
+    pub fn auto<F>(size: F, reserved_rows: u16) -> Self
+    where
+        F: FnOnce() -> (u16, u16),
+    {
+        let mut canvas = Self { reserved_rows, ..Self::new() };
+        canvas.fit_to(size);
+        canvas
     }
 
-    pub fn undo(&mut self) -> Option<grid::Segment> {
-        self.design.pop()
+    /// Resizes the canvas bounds to the screen size reported by `size`.
+    pub fn fit_to<F>(&mut self, size: F)
+    where
+        F: FnOnce() -> (u16, u16),
+    {
+        let (width, height) = size();
+        self.bounds = ((1, self.reserved_rows + 1).into(), (width, height).into());
     }
 
-    pub fn clear(&mut self) {
-        self.design.iter_mut().for_each(|segment| segment.clear());
+    pub fn bounds(&self) -> (grid::Point, grid::Point) {
+        self.bounds
     }
 
-    pub fn snapshot(&self) -> Vec<grid::Segment> {
-        self.design.clone()
+    pub fn contains(&self, point: grid::Point) -> bool {
+        let (start, end) = self.bounds;
+        (start.x..=end.x).contains(&point.x) && (start.y..=end.y).contains(&point.y)
     }
-}
 
-impl fmt::Display for Canvas {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.design.iter().try_for_each(|segment| write!(f, "{}", segment))
+    /// Returns the closest point within the canvas bounds.
+    pub fn clamp(&self, point: grid::Point) -> grid::Point {
+        let (start, end) = self.bounds;
+        // Unlike `clamp`, this doesn't panic on a terminal too small to leave any canvas rows
+        let within = |value: u16, low: u16, high: u16| cmp::max(cmp::min(value, high), low);
+        grid::Point::new(within(point.x, start.x, end.x), within(point.y, start.y, end.y))
+    }
+
+    pub fn set_toolbar_behavior(&mut self, behavior: ToolbarBehavior) {
+        self.toolbar_behavior = behavior;
+    }
+
+    pub fn toolbar_behavior(&self) -> ToolbarBehavior {
+        self.toolbar_behavior
+    }
+
+    /// Restricts drawing to the rectangle with the given corners, on top of the canvas bounds.
+    /// Cells outside of it are discarded as they are drawn.
+    pub fn set_clip(&mut self, clip: Option<(grid::Point, grid::Point)>) {
+        self.clip = clip;
+        let sketch = mem::take(&mut self.sketch);
+        self.sketch = self.clipped(sketch);
+    }
+
+    fn clipped(&self, segment: grid::Segment) -> grid::Segment {
+        match self.clip {
+            Some((start, end)) => segment.within_rect(start, end),
+            None => segment,
+        }
+    }
+
+    /// Sets the screen position of the canvas's top left corner, for drawing it within a part of
+    /// the terminal. Stroke positions, and therefore exports, stay relative to the canvas, while
+    /// segments returned for erasing are already placed on the screen.
+    pub fn set_origin(&mut self, origin: grid::Point) {
+        self.origin = origin;
+    }
+
+    /// Returns a copy of the segment moved from canvas to screen positions, leaving out any cells
+    /// that would land off the screen.
+    pub fn to_screen(&self, segment: &grid::Segment) -> grid::Segment {
+        let (dx, dy) = (i32::from(self.origin.x) - 1, i32::from(self.origin.y) - 1);
+        if (dx, dy) == (0, 0) {
+            return segment.clone();
+        }
+
+        let mut moved: grid::Segment = segment
+            .cells()
+            .iter()
+            .filter_map(|cell| Some(grid::Cell::new(cell.pos().offset(dx, dy)?, cell.content())))
+            .collect();
+        moved.set_format(segment.format());
+        moved
+    }
+
+    /// Returns the canvas position of a point on the screen, such as a mouse position. Points
+    /// above or left of the origin end up outside of the canvas bounds.
+    pub fn canvas_point(&self, screen: grid::Point) -> grid::Point {
+        let shift = |value: u16, origin: u16| value.saturating_add(1).saturating_sub(origin);
+        grid::Point::new(shift(screen.x, self.origin.x), shift(screen.y, self.origin.y))
+    }
+
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+        self.line_end = None;
+    }
+
+    pub fn cycle_tool(&mut self) -> Tool {
+        self.tool = self.tool.next();
+        self.line_end = None;
+        self.tool
+    }
+
+    /// Makes each line drawn start where the previous one ended, until the tool is changed.
+    pub fn set_line_chaining(&mut self, enabled: bool) {
+        self.line_chaining = enabled;
+        self.line_end = None;
+    }
+
+    /// Returns where a line pressed at the point starts, which is the end of the previous line
+    /// when chaining lines.
+    pub fn line_start(&self, point: grid::Point) -> grid::Point {
+        match self.tool {
+            Tool::Line => self.line_end.unwrap_or(point),
+            _ => point,
+        }
+    }
+
+    /// Sets the glyphs used for freehand strokes, from the slowest drag speed to the fastest.
+    /// An empty ramp disables speed based glyphs.
+    pub fn set_speed_glyphs(&mut self, ramp: Vec<char>) {
+        self.speed_glyphs = ramp;
+    }
+
+    pub fn speed_glyph(&self, from: grid::Point, to: grid::Point) -> Option<char> {
+        let distance = cmp::max(from.x.abs_diff(to.x), from.y.abs_diff(to.y)) as usize;
+        let index = cmp::min(distance.saturating_sub(1), self.speed_glyphs.len().checked_sub(1)?);
+        Some(self.speed_glyphs[index])
+    }
+
+    /// Draws freehand strokes with a flat nib held at `degrees` counterclockwise from horizontal,
+    /// so that they're thick when moving across the nib and thin when moving along it. `None`
+    /// draws them one cell wide.
+    pub fn set_nib_angle(&mut self, degrees: Option<f64>) {
+        self.nib_angle = degrees;
+    }
+
+    /// Widens the freehand stroke drawn moving from `from` to `to` according to the nib, copying
+    /// each cell along the nib's edge.
+    pub fn nibbed(
+        &self,
+        stroke: grid::Segment,
+        from: grid::Point,
+        to: grid::Point,
+    ) -> grid::Segment {
+        let nib = match self.nib_angle {
+            Some(degrees) if from != to => degrees.to_radians(),
+            _ => return stroke,
+        };
+
+        // Rows grow downwards, so angles are measured against a flipped y axis
+        let heading =
+            (f64::from(from.y) - f64::from(to.y)).atan2(f64::from(to.x) - f64::from(from.x));
+        let extra = ((heading - nib).sin().abs() * f64::from(NIB_WIDTH - 1)).round() as i32;
+        let (dx, dy) = (nib.cos().round() as i32, -nib.sin().round() as i32);
+
+        let mut nibbed = grid::Segment::new();
+        nibbed.set_format(stroke.format());
+        for cell in stroke.cells() {
+            nibbed.add(*cell);
+            for k in 1..=extra {
+                // Alternate sides, so that the nib stays centered on the stroke
+                let reach = if k % 2 == 1 { (k + 1) / 2 } else { -k / 2 };
+                if let Some(pos) = cell.pos().offset(dx * reach, dy * reach) {
+                    nibbed.add(grid::Cell::new(pos, cell.content()));
+                }
+            }
+        }
+        nibbed
+    }
+
+    /// Enables replacing freehand strokes that stay within `threshold` cells of a straight line
+    /// with that line.
+    pub fn set_auto_straighten(&mut self, threshold: Option<f64>) {
+        self.straighten_threshold = threshold;
+    }
+
+    /// Replaces the sketch of a freehand stroke started at `from` with a straight line, if it's
+    /// straight enough for auto straightening. Strokes ending where they started, or shorter than
+    /// `STRAIGHTEN_MIN_LENGTH`, are left alone. Returns the replaced sketch so it can be erased.
+    pub fn straighten_sketch(
+        &mut self,
+        tracer: &grid::Tracer,
+        from: grid::Point,
+    ) -> Option<grid::Segment> {
+        let threshold = self.straighten_threshold?;
+        let (_, last) = self.sketch.endpoints()?;
+        if from != last
+            && self.sketch.len() >= STRAIGHTEN_MIN_LENGTH
+            && self.sketch.max_deviation(from, last) < threshold
+        {
+            Some(self.replace_sketch(tracer.trace(from, last)))
+        } else {
+            None
+        }
+    }
+
+    pub fn add_guide(&mut self, orientation: Orientation, position: u16) {
+        let guide = Guide::new(orientation, position);
+        if !self.guides.contains(&guide) {
+            self.guides.push(guide);
+        }
+    }
+
+    pub fn remove_guide(&mut self, orientation: Orientation, position: u16) -> Option<Guide> {
+        let guide = Guide::new(orientation, position);
+        let index = self.guides.iter().position(|g| *g == guide)?;
+        Some(self.guides.remove(index))
+    }
+
+    pub fn guides(&self) -> &[Guide] {
+        &self.guides
+    }
+
+    /// Moves the point onto the closest graph paper node in graph mode, then onto any guide lying
+    /// within snapping distance of it, then onto any nearby stroke corner or edge midpoint when
+    /// geometry snapping is on.
+    pub fn snap(&self, point: grid::Point) -> grid::Point {
+        let point = self.graph_snap(point);
+        let point =
+            self.guides.iter().fold(point, |point, guide| guide.snap(point).unwrap_or(point));
+        self.geometry_snap(point).unwrap_or(point)
+    }
+
+    /// Shows graph paper with a node every `spacing` cells across and down, and snaps drawing to
+    /// its nodes. `None` turns graph mode off.
+    pub fn set_graph_mode(&mut self, spacing: Option<u16>) {
+        self.graph_spacing = spacing.filter(|spacing| *spacing > 0);
+    }
+
+    /// Returns the graph paper nodes within the canvas, in graph mode.
+    pub fn graph_paper(&self) -> Option<grid::Segment> {
+        let spacing = self.graph_spacing?;
+        let (start, end) = self.bounds;
+        let first = |from: u16| cmp::max(from.div_ceil(spacing), 1) * spacing;
+
+        let mut paper = grid::Segment::new();
+        for y in (first(start.y)..=end.y).step_by(usize::from(spacing)) {
+            for x in (first(start.x)..=end.x).step_by(usize::from(spacing)) {
+                paper.add(grid::Cell::new((x, y).into(), '.'));
+            }
+        }
+        paper.set_format(OVERLAY_FORMAT);
+        Some(paper)
+    }
+
+    fn graph_snap(&self, point: grid::Point) -> grid::Point {
+        let spacing = match self.graph_spacing {
+            Some(spacing) => spacing,
+            None => return point,
+        };
+
+        let nearest = |value: u16| {
+            let below = value - value % spacing;
+            let nearest = if value - below >= spacing - (value - below) {
+                below.saturating_add(spacing)
+            } else {
+                below
+            };
+            cmp::max(nearest, spacing)
+        };
+        self.clamp(grid::Point::new(nearest(point.x), nearest(point.y)))
+    }
+
+    /// Enables snapping to the corners and edge midpoints of the strokes' bounding boxes.
+    pub fn set_geometry_snap(&mut self, enabled: bool) {
+        self.geometry_snap = enabled;
+    }
+
+    /// Returns the closest stroke bounding box corner or edge midpoint within snapping distance
+    /// of the point, if geometry snapping is on.
+    pub fn geometry_snap(&self, point: grid::Point) -> Option<grid::Point> {
+        if !self.geometry_snap {
+            return None;
+        }
+
+        let distance =
+            |other: &grid::Point| cmp::max(point.x.abs_diff(other.x), point.y.abs_diff(other.y));
+        self.design
+            .iter()
+            .filter_map(grid::Segment::boundaries)
+            .flat_map(|(start, end)| {
+                let middle = grid::Point::new((start.x + end.x) / 2, (start.y + end.y) / 2);
+                [
+                    start,
+                    grid::Point::new(end.x, start.y),
+                    end,
+                    grid::Point::new(start.x, end.y),
+                    grid::Point::new(middle.x, start.y),
+                    grid::Point::new(end.x, middle.y),
+                    grid::Point::new(middle.x, end.y),
+                    grid::Point::new(start.x, middle.y),
+                ]
+            })
+            .filter(|snap| distance(snap) <= GEOMETRY_SNAP_DISTANCE)
+            .min_by_key(distance)
+    }
+
+    /// Sets the listener told about each stroke added to the drawing, and each one taken away or
+    /// brought back by undoing or redoing its addition. Other changes, such as clearing,
+    /// replacing glyphs, rotating, merging, decluttering, compacting or shapifying, aren't
+    /// streamed, so canvases kept in sync through deltas should stick to adding strokes.
+    pub fn on_commit<F>(&mut self, listener: F)
+    where
+        F: FnMut(&StrokeDelta) + 'static,
+    {
+        self.on_commit = Some(Box::new(listener));
+    }
+
+    pub fn on_preview<F>(&mut self, listener: F)
+    where
+        F: FnMut(&LinePreview) + 'static,
+    {
+        self.on_preview = Some(Box::new(listener));
+    }
+
+    /// Replaces the stroke in progress with a line from the cursor to the point, telling the
+    /// preview listener about it. Returns the previous stroke so that it can be erased.
+    pub fn preview_line(&mut self, tracer: &grid::Tracer, to: grid::Point) -> grid::Segment {
+        let from = self.cursor;
+        let line = tracer.trace(from, to);
+        if let Some(listener) = self.on_preview.as_mut() {
+            let first_step = line.cells().first().map_or(0.0, |cell| from.distance(cell.pos()));
+            listener(&LinePreview { from, to, length: first_step + line.path_length() });
+        }
+
+        self.replace_sketch(line)
+    }
+
+    /// Replays a stroke committed on another canvas, without notifying the commit listener.
+    /// `StrokeOp::Remove` deltas remove the latest stroke with the same cells, if there is one,
+    /// returning it so it can be erased.
+    pub fn apply_delta(&mut self, delta: StrokeDelta) -> Option<grid::Segment> {
+        match delta.op {
+            StrokeOp::Add => {
+                self.execute(Command::AddStroke { segment: delta.segment, locked: false });
+                None
+            }
+            StrokeOp::Remove => {
+                let index = self
+                    .design
+                    .iter()
+                    .rposition(|segment| segment.cells() == delta.segment.cells())?;
+                let segment = self.design[index].clone();
+                let removed = self.to_screen(&segment);
+                self.execute(Command::RemoveStroke {
+                    index,
+                    segment,
+                    locked: self.is_locked(index),
+                });
+                Some(removed)
+            }
+        }
+    }
+
+    /// Sets the colors of new strokes and the cursor marker contrast for the terminal background.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.sketch.set_format(theme.pen_format());
+    }
+
+    pub fn theme(&self) -> Theme {
+        self.theme
+    }
+
+    /// Sets characters to swap in when drawing and exporting, such as box drawing characters for
+    /// their ASCII counterparts. The strokes themselves are left unchanged.
+    pub fn set_render_substitutions(&mut self, substitutions: HashMap<char, char>) {
+        self.render_substitutions = substitutions;
+    }
+
+    /// Sets the number of columns between tab stops in labels and imported tables.
+    pub fn set_tab_width(&mut self, tab_width: u16) {
+        self.tab_width = tab_width;
+    }
+
+    pub fn set_caption(&mut self, caption: Option<String>) {
+        self.caption = caption;
+    }
+
+    /// Commits a stroke, failing if it would take the drawing past the cell cap.
+    pub fn add(&mut self, segment: grid::Segment) -> crate::Result {
+        let segment = self.clipped(segment);
+        if let Some(cap) = self.cell_cap {
+            let drawn = |segment: &grid::Segment| {
+                segment.cells().iter().filter(|cell| cell.content() != ' ').count()
+            };
+            let total: usize = self.design.iter().map(drawn).sum();
+            if total + drawn(&segment) > cap {
+                return Err(format!("drawing is limited to {} cells", cap).into());
+            }
+        }
+
+        self.push_stroke(segment);
+        Ok(())
+    }
+
+    /// Limits the number of drawn cells kept across all strokes. Drawing past the cap fails,
+    /// leaving the drawing unchanged. Blanks, and therefore eraser strokes, don't count, so that
+    /// erasing is always possible.
+    pub fn set_cell_cap(&mut self, cap: Option<usize>) {
+        self.cell_cap = cap;
+    }
+
+    /// Writes the segment out one cell at a time, pausing for `delay` after each, then adds it to
+    /// the drawing. `sleep` does the pausing, normally being `thread::sleep`.
+    pub fn draw_segment_animated<W, S>(
+        &mut self,
+        out: &mut W,
+        segment: grid::Segment,
+        delay: time::Duration,
+        mut sleep: S,
+    ) -> crate::Result
+    where
+        W: Write,
+        S: FnMut(time::Duration),
+    {
+        let segment = self.clipped(segment);
+        for cell in segment.cells() {
+            let mut step: grid::Segment = iter::once(*cell).collect();
+            step.set_format(segment.format());
+            write!(out, "{}", self.to_screen(&step))?;
+            out.flush()?;
+            sleep(delay);
+        }
+
+        self.add(segment)
+    }
+
+    /// Returns the stroke in progress, which isn't part of the drawing until committed.
+    pub fn sketch(&self) -> &grid::Segment {
+        &self.sketch
+    }
+
+    /// Adds a cell to the stroke in progress, which is committed by `commit_sketch`.
+    pub fn sketch_add(&mut self, cell: grid::Cell) {
+        self.sketch_extend(iter::once(cell).collect());
+    }
+
+    pub fn sketch_extend(&mut self, segment: grid::Segment) {
+        self.sketch += self.clipped(segment);
+    }
+
+    /// Replaces the stroke in progress, returning the previous one so that it can be erased.
+    pub fn replace_sketch(&mut self, mut segment: grid::Segment) -> grid::Segment {
+        segment.set_format(self.theme.pen_format());
+        let segment = self.clipped(segment);
+        let previous = mem::replace(&mut self.sketch, segment);
+        self.to_screen(&previous)
+    }
+
+    /// Returns the visible cells the eraser stroke in progress would remove, highlighted. Cells of
+    /// locked strokes are left out, as erasing spares them.
+    pub fn erase_preview(&self) -> grid::Segment {
+        let covered: HashSet<grid::Point> =
+            self.sketch.cells().iter().map(|cell| cell.pos()).collect();
+        let locked: grid::Segment = self.locked.iter().map(|index| &self.design[*index]).sum();
+        let mut preview: grid::Segment = self
+            .design
+            .iter()
+            .sum::<grid::Segment>()
+            .trimmed()
+            .cells()
+            .iter()
+            .filter(|cell| covered.contains(&cell.pos()))
+            .copied()
+            .collect();
+        preview = preview.difference(&locked);
+        preview.set_format(ERASE_PREVIEW_FORMAT);
+        preview
+    }
+
+    /// Removes the last `n` cells of the stroke in progress, returning them so that they can be
+    /// erased. The cursor moves back to the end of what's left.
+    pub fn undo_cells(&mut self, n: usize) -> crate::Result<grid::Segment> {
+        if self.sketch.is_empty() {
+            return Err("no stroke in progress".into());
+        }
+
+        let removed = self.sketch.split_off(self.sketch.len().saturating_sub(n));
+        if let Some((_, last)) = self.sketch.endpoints() {
+            self.cursor = last;
+        }
+        Ok(self.to_screen(&removed))
+    }
+
+    /// Commits the stroke in progress, as an eraser stroke when using the erase tool.
+    pub fn commit_sketch(&mut self) -> crate::Result {
+        if self.sketch.is_empty() {
+            return Ok(());
+        }
+
+        // The sketch is kept if committing fails, so that it can be erased
+        let sketch = self.sketch.clone();
+        match self.tool {
+            Tool::Erase => self.erase(sketch)?,
+            _ => self.add(sketch)?,
+        }
+        self.freehand_last = self.tool == Tool::Plot;
+        if self.tool == Tool::Line && self.line_chaining {
+            self.line_end = self.sketch.endpoints().map(|(_, end)| end);
+        }
+        self.sketch.clear();
+        Ok(())
+    }
+
+    /// Adds an eraser stroke, leaving out any cells that would cover locked strokes. Eraser
+    /// strokes aren't held to the cell cap.
+    pub fn erase(&mut self, segment: grid::Segment) -> crate::Result {
+        let locked: grid::Segment = self.locked.iter().map(|index| &self.design[*index]).sum();
+        let segment = self.clipped(segment.difference(&locked));
+        self.push_stroke(segment);
+        Ok(())
+    }
+
+    /// Erases every visible cell showing `glyph` with a single eraser stroke.
+    pub fn erase_glyph(&mut self, glyph: char) -> crate::Result {
+        let mut eraser: grid::Segment = self
+            .design
+            .iter()
+            .sum::<grid::Segment>()
+            .trimmed()
+            .cells()
+            .iter()
+            .filter(|cell| cell.content() == glyph)
+            .copied()
+            .collect();
+
+        if eraser.is_empty() {
+            return Err(format!("no '{}' to erase", glyph).into());
+        }
+
+        eraser.fill(' ');
+        self.erase(eraser)
+    }
+
+    /// Replaces `from` with `to` in every unlocked stroke, as a single command.
+    pub fn replace_glyph(&mut self, from: char, to: char) -> crate::Result {
+        // Blanks are erasures rather than glyphs
+        if from == ' ' {
+            return Err("can't replace blanks".into());
+        }
+
+        let substitution: HashMap<char, char> = iter::once((from, to)).collect();
+        let after: Vec<grid::Segment> = self
+            .design
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                if self.is_locked(index) {
+                    segment.clone()
+                } else {
+                    segment.substituted(&substitution)
+                }
+            })
+            .collect();
+
+        // Matches hidden under later strokes or erased don't count
+        let visible = |design: &[grid::Segment]| design.iter().sum::<grid::Segment>().trimmed();
+        let replaced = visible(&after).cells() != visible(&self.design).cells();
+        if !replaced {
+            return Err(format!("no '{}' to replace", from).into());
+        }
+
+        self.execute(Command::ReplaceAll {
+            before: self.design.clone(),
+            after,
+            locked_before: self.locked.clone(),
+            locked_after: self.locked.clone(),
+        });
+        Ok(())
+    }
+
+    pub fn set_locked(&mut self, index: usize, locked: bool) -> crate::Result {
+        if index >= self.design.len() {
+            return Err("no such stroke".into());
+        }
+
+        if locked {
+            self.locked.insert(index);
+        } else {
+            self.locked.remove(&index);
+        }
+        Ok(())
+    }
+
+    pub fn is_locked(&self, index: usize) -> bool {
+        self.locked.contains(&index)
+    }
+
+    /// Returns whether both strokes exist and don't share any point, so that merging them
+    /// wouldn't change how either looks.
+    pub fn can_merge(&self, a: usize, b: usize) -> bool {
+        match (self.design.get(a), self.design.get(b)) {
+            (Some(first), Some(second)) => a != b && !first.intersects(second),
+            _ => false,
+        }
+    }
+
+    /// Combines two disjoint strokes into one, in place of the later one.
+    pub fn merge_strokes(&mut self, a: usize, b: usize) -> crate::Result {
+        if !self.can_merge(a, b) {
+            return Err("strokes overlap or don't exist".into());
+        }
+        // A stroke has a single format, which would otherwise be lost for one of them
+        if self.design[a].format() != self.design[b].format() {
+            return Err("strokes have different formats".into());
+        }
+        if self.is_locked(a) || self.is_locked(b) {
+            return Err("stroke is locked".into());
+        }
+
+        let (first, second) = (cmp::min(a, b), cmp::max(a, b));
+        // The earlier stroke moves up to the later one, so it mustn't cover anything in between
+        if self.design[first + 1..second]
+            .iter()
+            .any(|segment| segment.intersects(&self.design[first]))
+        {
+            return Err("merging would change the drawing".into());
+        }
+
+        let mut merged = self.design[second].clone();
+        merged += self.design[first].clone();
+
+        let mut after = self.design.clone();
+        after[second] = merged.deduplicated();
+        after.remove(first);
+        let locked_after = self
+            .locked
+            .iter()
+            .map(|index| if *index > first { index - 1 } else { *index })
+            .collect();
+
+        self.execute(Command::ReplaceAll {
+            before: self.design.clone(),
+            after,
+            locked_before: self.locked.clone(),
+            locked_after,
+        });
+        Ok(())
+    }
+
+    pub fn connect_boxes(
+        &mut self,
+        tracer: &grid::Tracer,
+        a: &grid::Segment,
+        b: &grid::Segment,
+    ) -> crate::Result {
+        let connector = tracer.connect_boxes(a, b).ok_or("boxes overlap")?;
+        self.add(connector)
+    }
+
+    /// Adds a connector from `from` to `to` that goes around the visible cells in its way.
+    pub fn connect_routed(
+        &mut self,
+        tracer: &grid::Tracer,
+        from: grid::Point,
+        to: grid::Point,
+    ) -> crate::Result {
+        let obstacles: HashSet<grid::Point> = self
+            .design
+            .iter()
+            .sum::<grid::Segment>()
+            .trimmed()
+            .cells()
+            .iter()
+            .map(|cell| cell.pos())
+            .collect();
+
+        // Bound the search to keep it fast, without leaving the canvas
+        let (start, end) = self.bounds;
+        let area = (
+            grid::Point::new(
+                cmp::max(cmp::min(from.x, to.x).saturating_sub(ROUTE_MARGIN), start.x),
+                cmp::max(cmp::min(from.y, to.y).saturating_sub(ROUTE_MARGIN), start.y),
+            ),
+            grid::Point::new(
+                cmp::min(cmp::max(from.x, to.x).saturating_add(ROUTE_MARGIN), end.x),
+                cmp::min(cmp::max(from.y, to.y).saturating_add(ROUTE_MARGIN), end.y),
+            ),
+        );
+
+        let connector =
+            tracer.route(from, to, &obstacles, area).ok_or("no route between the points")?;
+        self.add(connector)
+    }
+
+    pub fn draw_polygon(
+        &mut self,
+        tracer: &grid::Tracer,
+        center: grid::Point,
+        radius: u16,
+        sides: u16,
+    ) -> crate::Result {
+        if sides < 3 {
+            return Err("a polygon needs at least 3 sides".into());
+        }
+
+        let polygon =
+            tracer.polygon(center, radius, sides).ok_or("polygon would leave the canvas")?;
+        self.add(polygon)
+    }
+
+    /// Adds a rectangle around everything drawn as a single stroke, with its edges `padding` cells
+    /// out from the drawing's bounds. Does nothing if the drawing is empty.
+    pub fn frame(&mut self, tracer: &grid::Tracer, padding: u16) -> crate::Result {
+        let visible = self.design.iter().sum::<grid::Segment>().trimmed();
+        let (start, end) = match visible.boundaries() {
+            Some(boundaries) => boundaries,
+            None => return Ok(()),
+        };
+
+        let padding = i32::from(padding);
+        let (start, end) = start
+            .offset(-padding, -padding)
+            .zip(end.offset(padding, padding))
+            .ok_or("frame would leave the canvas")?;
+        self.add(tracer.rectangle(start, end))
+    }
+
+    /// Adds a table of `cols` by `rows` boxes of `cell_w` by `cell_h` cells as a single stroke.
+    pub fn draw_grid(
+        &mut self,
+        top_left: grid::Point,
+        cell_w: u16,
+        cell_h: u16,
+        cols: u16,
+        rows: u16,
+    ) -> crate::Result {
+        if cols == 0 || rows == 0 {
+            return Err("a grid needs at least one column and row".into());
+        }
+
+        let table = grid::Segment::grid(top_left, cell_w, cell_h, cols, rows)
+            .ok_or("grid would leave the canvas")?;
+        self.add(table)
+    }
+
+    /// Adds copies of `stamp` every `spacing` cells along the straight path from `from` to `to`,
+    /// with the top left corner of each copy on the path, as a single stroke.
+    pub fn stamp_along(
+        &mut self,
+        stamp: &grid::Segment,
+        from: grid::Point,
+        to: grid::Point,
+        spacing: u16,
+    ) -> crate::Result {
+        if spacing == 0 {
+            return Err("spacing must be at least 1".into());
+        }
+        let (corner, _) = stamp.boundaries().ok_or("stamp is empty")?;
+
+        let path = grid::Tracer::default().trace(from, to);
+        let points = iter::once(from).chain(path.cells().iter().map(|cell| cell.pos()));
+        let mut stamps = points
+            .step_by(usize::from(spacing))
+            .map(|point| {
+                let (dx, dy) = (
+                    i32::from(point.x) - i32::from(corner.x),
+                    i32::from(point.y) - i32::from(corner.y),
+                );
+                stamp.translated(dx, dy).ok_or("stamp would leave the canvas")
+            })
+            .collect::<Result<Vec<grid::Segment>, _>>()?
+            .iter()
+            .sum::<grid::Segment>();
+
+        stamps.set_format(stamp.format());
+        self.add(stamps)
+    }
+
+    pub fn repeat_last(&mut self, dx: i32, dy: i32) -> crate::Result {
+        let last = self.design.last().ok_or("nothing to repeat")?;
+        let copy = last.translated(dx, dy).ok_or("repeated stroke would leave the canvas")?;
+        self.add(copy)
+    }
+
+    /// Turns the unlocked strokes a quarter clockwise as a whole, keeping their top left corner in
+    /// place. Locked strokes stay as they are.
+    pub fn rotate_all(&mut self) -> crate::Result {
+        let (start, end) = self
+            .design
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !self.is_locked(*index))
+            .map(|(_, segment)| segment)
+            .sum::<grid::Segment>()
+            .boundaries()
+            .ok_or("nothing to rotate")?;
+
+        let rotated = self
+            .design
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| {
+                if self.is_locked(index) {
+                    Some(segment.clone())
+                } else {
+                    segment.rotate_90(start, end)
+                }
+            })
+            .collect::<Option<Vec<grid::Segment>>>()
+            .filter(|rotated| {
+                rotated
+                    .iter()
+                    .filter_map(grid::Segment::boundaries)
+                    .all(|(first, last)| self.contains(first) && self.contains(last))
+            })
+            .ok_or("rotated drawing would leave the canvas")?;
+
+        self.execute(Command::ReplaceAll {
+            before: self.design.clone(),
+            after: rotated,
+            locked_before: self.locked.clone(),
+            locked_after: self.locked.clone(),
+        });
+        Ok(())
+    }
+
+    /// Moves strokes overlapping earlier ones, or coming within `gap` cells of them, by the
+    /// shortest horizontal or vertical shift that keeps them apart. This is best-effort: locked
+    /// strokes, blank eraser strokes and strokes partly erased by later ones stay put, and strokes
+    /// that can't be moved clear within `DECLUTTER_MAX_SHIFT` cells are left where they are.
+    pub fn declutter(&mut self, gap: u16) -> crate::Result {
+        if self.design.iter().all(|segment| segment.trimmed().is_empty()) {
+            return Err("nothing to declutter".into());
+        }
+
+        let gap = i32::from(gap);
+        let mut halo: HashSet<grid::Point> = HashSet::new();
+        let mut decluttered = Vec::with_capacity(self.design.len());
+        for (index, segment) in self.design.iter().enumerate() {
+            if segment.trimmed().is_empty() {
+                decluttered.push(segment.clone());
+                continue;
+            }
+
+            // Moving a stroke out from under an eraser would bring back what it erased
+            let erased = self.design[index + 1..]
+                .iter()
+                .any(|later| later.trimmed().is_empty() && later.intersects(segment));
+            let fixed = erased || self.locked.contains(&index);
+
+            let shifts = (1..=DECLUTTER_MAX_SHIFT)
+                .flat_map(|shift| vec![(shift, 0), (0, shift), (-shift, 0), (0, -shift)]);
+            let placed = iter::once((0, 0))
+                .chain(shifts)
+                .filter(|_| !fixed)
+                .filter_map(|(dx, dy)| segment.translated(dx, dy))
+                .find(|moved| {
+                    moved.trimmed().cells().iter().all(|cell| !halo.contains(&cell.pos()))
+                        && moved.boundaries().is_some_and(|(first, last)| {
+                            self.contains(first) && self.contains(last)
+                        })
+                })
+                .unwrap_or_else(|| segment.clone());
+
+            // Later strokes keep clear of everything within `gap` of the ones already placed
+            for cell in placed.trimmed().cells() {
+                let around = (-gap..=gap).flat_map(|dy| (-gap..=gap).map(move |dx| (dx, dy)));
+                halo.extend(around.filter_map(|(dx, dy)| cell.pos().offset(dx, dy)));
+            }
+            decluttered.push(placed);
+        }
+
+        let moved = self
+            .design
+            .iter()
+            .zip(&decluttered)
+            .any(|(before, after)| before.boundaries() != after.boundaries());
+        if moved {
+            self.execute(Command::ReplaceAll {
+                before: self.design.clone(),
+                after: decluttered,
+                locked_before: self.locked.clone(),
+                locked_after: self.locked.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Drops the cells of strokes that are erased or drawn over, and then any strokes left with
+    /// nothing visible, without changing what the drawing looks like. This can be undone.
+    pub fn compact_history(&mut self) {
+        let mut owners: HashMap<grid::Point, usize> = HashMap::new();
+        for (index, segment) in self.design.iter().enumerate() {
+            for cell in segment.cells() {
+                owners.insert(cell.pos(), index);
+            }
+        }
+
+        let mut compacted = Vec::new();
+        let mut locked = HashSet::new();
+        for (index, segment) in self.design.iter().enumerate() {
+            let mut visible: grid::Segment = segment
+                .trimmed()
+                .cells()
+                .iter()
+                .filter(|cell| owners[&cell.pos()] == index)
+                .copied()
+                .collect();
+            visible.set_format(segment.format());
+            if visible.is_empty() {
+                continue;
+            }
+
+            if self.locked.contains(&index) {
+                locked.insert(compacted.len());
+            }
+            compacted.push(visible);
+        }
+
+        let unchanged = compacted.len() == self.design.len()
+            && compacted
+                .iter()
+                .zip(&self.design)
+                .all(|(after, before)| after.len() == before.len());
+        if !unchanged {
+            self.execute(Command::ReplaceAll {
+                before: self.design.clone(),
+                after: compacted,
+                locked_before: self.locked.clone(),
+                locked_after: locked,
+            });
+        }
+    }
+
+    /// Adds a copy of the last stroke, mirrored across the column `axis`.
+    pub fn duplicate_mirrored(&mut self, axis: u16) -> crate::Result {
+        let last = self.design.last().ok_or("nothing to mirror")?;
+        let copy = last.flip_horizontal(axis).ok_or("mirrored stroke would leave the canvas")?;
+        self.add(copy)
+    }
+
+    /// Replaces the last stroke with a clean line or rectangle if it roughly traces one, leaving
+    /// other shapes unchanged. Only a stroke just drawn freehand can be shapified, not text,
+    /// tables, banners or eraser strokes. Returns the replaced stroke so it can be erased.
+    pub fn shapify_last(&mut self, tracer: &grid::Tracer) -> crate::Result<Option<grid::Segment>> {
+        let index = self.design.len().checked_sub(1).ok_or("nothing to shapify")?;
+        if !self.freehand_last {
+            return Err("last stroke wasn't drawn freehand".into());
+        }
+        if self.is_locked(index) {
+            return Err("stroke is locked".into());
+        }
+
+        let stroke = &self.design[index];
+        if stroke.trimmed().is_empty() {
+            return Err("stroke is empty".into());
+        }
+        // Blanks erase what's below them, which a clean shape drawn in their place wouldn't do
+        if stroke.cells().iter().any(|cell| cell.content() == ' ') {
+            return Ok(None);
+        }
+
+        let (first, last) = stroke.endpoints().ok_or("stroke is empty")?;
+        let mut shape = if stroke.len() > 1
+            && first != last
+            && stroke.max_deviation(first, last) <= f64::from(SHAPE_TOLERANCE)
+        {
+            tracer.trace(first, last)
+        } else {
+            match Self::rectangle_traced_by(tracer, stroke) {
+                Some((start, end)) => tracer.rectangle(start, end),
+                None => return Ok(None),
+            }
+        };
+
+        shape.set_format(stroke.format());
+        let before = stroke.clone();
+        let replaced = self.to_screen(&before);
+        self.execute(Command::ReplaceStroke { index, before, after: shape });
+        Ok(Some(replaced))
+    }
+
+    /// Returns the corners of the rectangle the stroke runs along, if it stays near its bounding
+    /// box border and passes by most of it.
+    fn rectangle_traced_by(
+        tracer: &grid::Tracer,
+        stroke: &grid::Segment,
+    ) -> Option<(grid::Point, grid::Point)> {
+        let (start, end) = stroke.boundaries()?;
+        // Leave room for cells that are clearly inside the border
+        if end.x - start.x <= 2 * SHAPE_TOLERANCE + 1 || end.y - start.y <= 2 * SHAPE_TOLERANCE + 1
+        {
+            return None;
+        }
+
+        let near = |a: grid::Point, b: grid::Point| {
+            a.x.abs_diff(b.x) <= SHAPE_TOLERANCE && a.y.abs_diff(b.y) <= SHAPE_TOLERANCE
+        };
+        let on_border = |point: grid::Point| {
+            [point.x.abs_diff(start.x), point.x.abs_diff(end.x)]
+                .iter()
+                .chain([point.y.abs_diff(start.y), point.y.abs_diff(end.y)].iter())
+                .any(|distance| *distance <= SHAPE_TOLERANCE)
+        };
+
+        if !stroke.cells().iter().all(|cell| on_border(cell.pos())) {
+            return None;
+        }
+
+        let border = tracer.rectangle(start, end);
+        let covered = border
+            .cells()
+            .iter()
+            .filter(|edge| stroke.cells().iter().any(|cell| near(cell.pos(), edge.pos())))
+            .count();
+
+        if covered as f64 >= border.len() as f64 * SHAPE_COVERAGE {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    /// Adds the text centered within the rectangle with the given corners, cutting off whatever
+    /// doesn't fit.
+    pub fn place_label_centered(
+        &mut self,
+        text: &str,
+        within: (grid::Point, grid::Point),
+    ) -> crate::Result {
+        let (a, b) = within;
+        let (start, end) = (
+            grid::Point::new(cmp::min(a.x, b.x), cmp::min(a.y, b.y)),
+            grid::Point::new(cmp::max(a.x, b.x), cmp::max(a.y, b.y)),
+        );
+
+        let width = end.x - start.x + 1;
+        let pos = grid::Point::new(
+            start.x
+                + width.saturating_sub(grid::text_width_with_tab_width(text, self.tab_width)) / 2,
+            start.y + (end.y - start.y) / 2,
+        );
+
+        let label = grid::Segment::from_str_with_tab_width(
+            pos,
+            text,
+            self.theme.pen_format(),
+            self.tab_width,
+        )
+        .within_rect(start, end);
+        if label.is_empty() {
+            return Err("label is empty".into());
+        }
+
+        self.add(label)
+    }
+
+    /// Adds the text in large letters three rows tall, with its top left corner at `at`. Only
+    /// letters, digits and spaces can be drawn.
+    pub fn banner(&mut self, text: &str, at: grid::Point) -> crate::Result {
+        let mut banner = font::banner(text, at)?;
+        if banner.is_empty() {
+            return Err("banner is empty".into());
+        }
+
+        banner.set_format(self.theme.pen_format());
+        self.add(banner)
+    }
+
+    /// Adds the lines and polylines of the SVG drawing as a single stroke, with SVG coordinates
+    /// multiplied by `scale` to give cell offsets from `at`.
+    pub fn import_svg_lines(
+        &mut self,
+        tracer: &grid::Tracer,
+        svg: &str,
+        at: grid::Point,
+        scale: f64,
+    ) -> crate::Result {
+        let polylines = svg::polylines(svg)?;
+        if polylines.is_empty() {
+            return Err("no lines in SVG".into());
+        }
+
+        let mut stroke = grid::Segment::new();
+        for polyline in polylines {
+            let points = polyline
+                .iter()
+                .map(|(x, y)| {
+                    at.offset((x * scale).round() as i32, (y * scale).round() as i32)
+                        .ok_or("SVG lines would leave the canvas")
+                })
+                .collect::<Result<Vec<grid::Point>, _>>()?;
+            stroke += tracer.trace_path(&points);
+        }
+        self.add(stroke)
+    }
+
+    /// Adds the rows as a plain text table with aligned columns, the first row being a header set
+    /// apart by a separator line.
+    pub fn import_table(&mut self, rows: &[Vec<String>], at: grid::Point) -> crate::Result {
+        let columns = rows.iter().map(Vec::len).max().filter(|columns| *columns > 0);
+        let columns = columns.ok_or("table is empty")?;
+        let text_width = |text: &str| grid::text_width_with_tab_width(text, self.tab_width);
+        let widths: Vec<u16> = (0..columns)
+            .map(|column| {
+                let width = |row: &Vec<String>| row.get(column).map_or(0, |text| text_width(text));
+                rows.iter().map(width).max().unwrap_or_default()
+            })
+            .collect();
+
+        let mut lines: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let cells = widths.iter().enumerate().map(|(column, width)| {
+                    let text = row.get(column).map_or("", String::as_str);
+                    let padding = usize::from(width - text_width(text));
+                    format!("{}{}", text, " ".repeat(padding))
+                });
+                cells.collect::<Vec<String>>().join(" | ")
+            })
+            .collect();
+
+        let separator = widths.iter().map(|width| "-".repeat(usize::from(*width)));
+        lines.insert(1, separator.collect::<Vec<String>>().join("-+-"));
+
+        let format = self.theme.pen_format();
+        let table = lines
+            .iter()
+            .enumerate()
+            .map(|(line, text)| {
+                let pos = at.offset(0, line as i32).ok_or("table would leave the canvas")?;
+                let text = text.trim_end();
+                Ok(grid::Segment::from_str_with_tab_width(pos, text, format, self.tab_width))
+            })
+            .collect::<crate::Result<Vec<grid::Segment>>>()?;
+
+        let mut table: grid::Segment = table.iter().sum();
+        table.set_format(format);
+        self.add(table)
+    }
+
+    /// Shows a rectangle around the given stroke, which isn't part of the drawing.
+    pub fn outline_segment(&mut self, tracer: &grid::Tracer, index: usize) -> crate::Result {
+        let stroke = self.design.get(index).ok_or("no such stroke")?;
+        let (start, end) = stroke.boundaries().ok_or("stroke is empty")?;
+
+        let mut outline = tracer
+            .rectangle(start.offset(-1, -1).unwrap_or(start), end.offset(1, 1).unwrap_or(end));
+        outline.set_format(OVERLAY_FORMAT);
+        self.outline = Some(outline);
+        Ok(())
+    }
+
+    /// Sets the glyph and format marking the cursor position, or disables the marker.
+    pub fn set_cursor_marker(&mut self, marker: Option<(char, terminal::Format)>) {
+        self.cursor_marker = marker;
+    }
+
+    /// Returns the cursor marker, which isn't part of the drawing.
+    pub fn cursor_marker(&self) -> Option<grid::Segment> {
+        let (glyph, mut format) = self.cursor_marker?;
+        if self.theme == Theme::Light {
+            // Light colors meant for dark backgrounds are used as a highlight instead
+            format = terminal::Format::new(format.fg_color, LIGHT_PEN_FORMAT.fg_color);
+        }
+
+        let mut marker = grid::Segment::new();
+        marker.add(grid::Cell::new(self.cursor, glyph));
+        marker.set_format(format);
+        Some(marker)
+    }
+
+    /// Removes the stroke outline, returning it so that it can be erased.
+    pub fn clear_outline(&mut self) -> Option<grid::Segment> {
+        let outline = self.outline.take()?;
+        Some(self.to_screen(&outline))
+    }
+
+    /// Reverts the last command, returning the cells it removed from the drawing so that they
+    /// can be erased.
+    pub fn undo(&mut self) -> Option<grid::Segment> {
+        let mut command = self.history.pop()?;
+        self.freehand_last = false;
+        let removed = self.revert(&mut command);
+        if let Command::AddStroke { ref segment, .. } = command {
+            self.notify(StrokeOp::Remove, segment);
+        }
+
+        self.redo_stack.push(command);
+        Some(self.to_screen(&removed))
+    }
+
+    /// Reapplies the last undone command, returning the cells it removed from the drawing so that
+    /// they can be erased.
+    pub fn redo(&mut self) -> Option<grid::Segment> {
+        let command = self.redo_stack.pop()?;
+        self.freehand_last = false;
+        if let Command::AddStroke { ref segment, .. } = command {
+            self.notify(StrokeOp::Add, segment);
+        }
+
+        let removed = self.apply(&command);
+        self.history.push(command);
+        Some(self.to_screen(&removed))
+    }
+
+    /// Undoes or redoes commands until `n` commands have been applied, returning the cells they
+    /// removed from the drawing so that they can be erased.
+    pub fn jump_to(&mut self, n: usize) -> crate::Result<grid::Segment> {
+        if n > self.history.len() + self.redo_stack.len() {
+            return Err("no such point in history".into());
+        }
+
+        let mut removed = grid::Segment::new();
+        while self.history.len() > n {
+            removed += self.undo().unwrap_or_default();
+        }
+        while self.history.len() < n {
+            removed += self.redo().unwrap_or_default();
+        }
+        Ok(removed)
+    }
+
+    /// Returns the commands applied to the drawing, oldest first.
+    pub fn history(&self) -> &[Command] {
+        &self.history
+    }
+
+    /// Returns the commands undone, the next one to redo being last.
+    pub fn redo_stack(&self) -> &[Command] {
+        &self.redo_stack
+    }
+
+    /// Removes every stroke that isn't locked, along with the stroke in progress.
+    pub fn clear(&mut self) {
+        self.sketch.clear();
+        self.line_end = None;
+        if self.design.len() > self.locked.len() {
+            self.execute(Command::ClearAll {
+                strokes: self.design.clone(),
+                locked: self.locked.clone(),
+            });
+        }
+    }
+
+    pub fn strokes(&self) -> &[grid::Segment] {
+        &self.design
+    }
+
+    pub fn stroke_cells(&self, index: usize) -> Option<&[grid::Cell]> {
+        self.design.get(index).map(grid::Segment::cells)
+    }
+
+    /// Returns the visible cells inside the freehand loop traced through the given points.
+    pub fn lasso(&self, path: &[grid::Point]) -> grid::Segment {
+        self.design.iter().sum::<grid::Segment>().trimmed().within_polygon(path)
+    }
+
+    /// Returns the visible cells inside the rectangle, its edges included, in reading order.
+    pub fn cells_in(&self, top_left: grid::Point, bottom_right: grid::Point) -> Vec<grid::Cell> {
+        let visible = self.design.iter().sum::<grid::Segment>().trimmed();
+        let mut cells = visible.within_rect(top_left, bottom_right).cells().to_vec();
+        cells.sort_by_key(|cell| (cell.pos().y, cell.pos().x));
+        cells
+    }
+
+    /// Groups the visible cells into shapes of touching cells, diagonals included, regardless of
+    /// the strokes that drew them. Shapes are ordered by their first cell drawn.
+    pub fn components(&self) -> Vec<grid::Segment> {
+        let visible = self.design.iter().sum::<grid::Segment>().trimmed();
+        let glyphs: HashMap<grid::Point, char> =
+            visible.cells().iter().map(|cell| (cell.pos(), cell.content())).collect();
+
+        let mut seen = HashSet::new();
+        let mut components = Vec::new();
+        for cell in visible.cells() {
+            if !seen.insert(cell.pos()) {
+                continue;
+            }
+
+            let mut component = grid::Segment::new();
+            let mut pending = vec![cell.pos()];
+            while let Some(pos) = pending.pop() {
+                component.add(grid::Cell::new(pos, glyphs[&pos]));
+                let neighbors = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter_map(|(dx, dy)| pos.offset(dx, dy));
+                for neighbor in neighbors {
+                    if glyphs.contains_key(&neighbor) && seen.insert(neighbor) {
+                        pending.push(neighbor);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
+    }
+
+    pub fn stats(&self) -> CanvasStats {
+        let visible = self.design.iter().sum::<grid::Segment>().trimmed();
+        let glyphs: HashSet<char> = visible.cells().iter().map(|cell| cell.content()).collect();
+
+        CanvasStats {
+            strokes: self.design.iter().filter(|segment| !segment.is_empty()).count(),
+            total_cells: self.design.iter().map(grid::Segment::len).sum(),
+            bounds: visible.boundaries(),
+            distinct_glyphs: glyphs.len(),
+        }
+    }
+
+    /// Returns the distinct foreground colors of the strokes, in order of first use. The terminal's
+    /// default color and eraser strokes, which show nothing, are left out.
+    pub fn colors_used(&self) -> Vec<terminal::Color> {
+        let mut colors = Vec::new();
+        for segment in self.design.iter().filter(|segment| !segment.trimmed().is_empty()) {
+            let color = segment.format().fg_color;
+            if color != terminal::Color::Reset && !colors.contains(&color) {
+                colors.push(color);
+            }
+        }
+        colors
+    }
+
+    pub fn render_into(&self, buf: &mut [Vec<char>]) {
+        self.design
+            .iter()
+            .for_each(|segment| segment.substituted(&self.render_substitutions).render_into(buf));
+    }
+
+    /// Returns the escape sequences drawing the current frame of the canvas, being the graph
+    /// paper, guides, drawing, stroke in progress and cursor marker, as written to the screen.
+    /// Printing them to a terminal of the same size redraws the canvas as it is.
+    pub fn to_ansi(&self) -> String {
+        let (start, end) = self.bounds;
+        let mut frame = String::new();
+        if let Some(paper) = self.graph_paper() {
+            frame += &self.to_screen(&paper).to_string();
+        }
+        for guide in &self.guides {
+            frame += &self.to_screen(&guide.segment(start, end)).to_string();
+        }
+        frame += &self.to_string();
+        if let Some(marker) = self.cursor_marker() {
+            frame += &self.to_screen(&marker).to_string();
+        }
+        frame
+    }
+
+    /// Repaints the canvas from scratch, blanking its whole area before writing the frame, to
+    /// recover from anything else having written over it. Unlike the frame written after each
+    /// change, this also wipes stray output between the strokes. The toolbar is not part of
+    /// the canvas, so callers must redraw it afterwards.
+    pub fn refresh<W: Write>(&self, out: &mut W) -> crate::Result {
+        let (start, end) = self.bounds;
+        let mut blank = grid::Segment::filled_rect(start, end, grid::FillPattern::Solid(' '));
+        blank.set_format(self.theme.pen_format());
+        write!(out, "{}{}", self.to_screen(&blank), self.to_ansi())?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn export(&self, exporter: &impl export::Exporter) -> String {
+        let blueprint = self.design.iter().sum::<grid::Segment>();
+        let blueprint = blueprint.substituted(&self.render_substitutions);
+        let content = exporter.export(&blueprint);
+        match self.caption {
+            Some(ref caption) => format!("{}\n\n{}", caption, content),
+            None => content,
+        }
+    }
+
+    /// Exports the drawing as a Markdown code block, tagged with the language if given.
+    pub fn export_markdown(&self, lang: Option<&str>) -> String {
+        self.export(&export::Markdown::new(lang))
+    }
+
+    /// Returns a plain text overview of the drawing shrunk to fit within `max_w` by `max_h`
+    /// characters, each showing the most common glyph in its block of cells.
+    pub fn export_preview(&self, max_w: u16, max_h: u16) -> String {
+        let visible = self.design.iter().sum::<grid::Segment>().trimmed();
+        let (start, end) = match visible.boundaries() {
+            Some(boundaries) => boundaries,
+            None => return String::new(),
+        };
+
+        let block = |size: u16, max: u16| cmp::max(size.div_ceil(cmp::max(max, 1)), 1);
+        let (block_w, block_h) =
+            (block(end.x - start.x + 1, max_w), block(end.y - start.y + 1, max_h));
+
+        let mut blocks: HashMap<grid::Point, Vec<char>> = HashMap::new();
+        for cell in visible.substituted(&self.render_substitutions).cells() {
+            let pos = grid::Point::new(
+                (cell.pos().x - start.x) / block_w + 1,
+                (cell.pos().y - start.y) / block_h + 1,
+            );
+            blocks.entry(pos).or_default().push(cell.content());
+        }
+
+        let preview: grid::Segment = blocks
+            .iter()
+            .map(|(pos, glyphs)| {
+                let count = |glyph: &char| glyphs.iter().filter(|other| *other == glyph).count();
+                // Ties go to the glyph drawn first
+                let glyph = glyphs.iter().rev().max_by_key(|glyph| count(glyph)).unwrap_or(&' ');
+                grid::Cell::new(*pos, *glyph)
+            })
+            .collect();
+
+        preview.into()
+    }
+
+    /// Returns a plain text image of the drawing shrunk by `block` cells in each direction, each
+    /// character shading its block of cells by how many of them are drawn, from blank to `@`.
+    pub fn export_density(&self, block: u16) -> String {
+        let visible = self.design.iter().sum::<grid::Segment>().trimmed();
+        let start = match visible.boundaries() {
+            Some((start, _)) => start,
+            None => return String::new(),
+        };
+
+        let block = cmp::max(block, 1);
+        let mut counts: HashMap<grid::Point, usize> = HashMap::new();
+        for cell in visible.cells() {
+            let pos = grid::Point::new(
+                (cell.pos().x - start.x) / block + 1,
+                (cell.pos().y - start.y) / block + 1,
+            );
+            *counts.entry(pos).or_default() += 1;
+        }
+
+        let area = f64::from(block) * f64::from(block);
+        let steps = (DENSITY_RAMP.len() - 1) as f64;
+        let image: grid::Segment = counts
+            .into_iter()
+            .map(|(pos, count)| {
+                // Any drawn cell shades its block, however sparse
+                let index = (count as f64 / area * steps).ceil() as usize;
+                grid::Cell::new(pos, DENSITY_RAMP[cmp::min(index, DENSITY_RAMP.len() - 1)])
+            })
+            .collect();
+
+        image.into()
+    }
+
+    fn execute(&mut self, command: Command) {
+        self.freehand_last = false;
+        self.apply(&command);
+        self.history.push(command);
+        self.redo_stack.clear();
+    }
+
+    fn apply(&mut self, command: &Command) -> grid::Segment {
+        match command {
+            Command::AddStroke { segment, locked } => {
+                if *locked {
+                    self.locked.insert(self.design.len());
+                }
+                self.design.push(segment.clone());
+                grid::Segment::new()
+            }
+            Command::RemoveStroke { index, segment, .. } => {
+                self.design.remove(*index);
+                self.locked = self
+                    .locked
+                    .iter()
+                    .filter(|locked| *locked != index)
+                    .map(|locked| if locked > index { locked - 1 } else { *locked })
+                    .collect();
+                segment.clone()
+            }
+            Command::ClearAll { strokes, locked } => {
+                let (kept, removed): (Vec<_>, Vec<_>) =
+                    strokes.iter().enumerate().partition(|(index, _)| locked.contains(index));
+                self.design = kept.into_iter().map(|(_, segment)| segment.clone()).collect();
+                self.locked = (0..self.design.len()).collect();
+                removed.into_iter().map(|(_, segment)| segment).sum()
+            }
+            Command::ReplaceStroke { index, before, after } => {
+                self.design[*index] = after.clone();
+                before.clone()
+            }
+            Command::ReplaceAll { before, after, locked_after, .. } => {
+                self.design = after.clone();
+                self.locked = locked_after.clone();
+                before.iter().sum()
+            }
+        }
+    }
+
+    /// Reverts the command, recording in it any lock the stroke it added has gained since, so
+    /// that redoing it restores the lock.
+    fn revert(&mut self, command: &mut Command) -> grid::Segment {
+        match command {
+            Command::AddStroke { locked, .. } => {
+                let segment = self.design.pop().unwrap_or_default();
+                *locked = self.locked.remove(&self.design.len());
+                segment
+            }
+            Command::RemoveStroke { index, segment, locked } => {
+                self.design.insert(*index, segment.clone());
+                self.locked = self
+                    .locked
+                    .iter()
+                    .map(|other| if other >= index { other + 1 } else { *other })
+                    .collect();
+                if *locked {
+                    self.locked.insert(*index);
+                }
+                grid::Segment::new()
+            }
+            Command::ClearAll { strokes, locked } => {
+                self.design = strokes.clone();
+                self.locked = locked.clone();
+                grid::Segment::new()
+            }
+            Command::ReplaceStroke { index, before, after } => {
+                self.design[*index] = before.clone();
+                after.clone()
+            }
+            Command::ReplaceAll { before, after, locked_before, .. } => {
+                self.design = before.clone();
+                self.locked = locked_before.clone();
+                after.iter().sum()
+            }
+        }
+    }
+
+    fn push_stroke(&mut self, segment: grid::Segment) {
+        self.notify(StrokeOp::Add, &segment);
+        self.execute(Command::AddStroke { segment, locked: false });
+    }
+
+    fn notify(&mut self, op: StrokeOp, segment: &grid::Segment) {
+        if let Some(listener) = self.on_commit.as_mut() {
+            listener(&StrokeDelta::new(segment.clone(), op));
+        }
+    }
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Canvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canvas")
+            .field("cursor", &self.cursor)
+            .field("origin", &self.origin)
+            .field("bounds", &self.bounds)
+            .field("reserved_rows", &self.reserved_rows)
+            .field("toolbar_behavior", &self.toolbar_behavior)
+            .field("tool", &self.tool)
+            .field("theme", &self.theme)
+            .field("clip", &self.clip)
+            .field("design", &self.design)
+            .field("freehand_last", &self.freehand_last)
+            .field("cell_cap", &self.cell_cap)
+            .field("sketch", &self.sketch)
+            .field("history", &self.history)
+            .field("redo_stack", &self.redo_stack)
+            .field("locked", &self.locked)
+            .field("outline", &self.outline)
+            .field("cursor_marker", &self.cursor_marker)
+            .field("guides", &self.guides)
+            .field("geometry_snap", &self.geometry_snap)
+            .field("graph_spacing", &self.graph_spacing)
+            .field("line_chaining", &self.line_chaining)
+            .field("line_end", &self.line_end)
+            .field("speed_glyphs", &self.speed_glyphs)
+            .field("nib_angle", &self.nib_angle)
+            .field("straighten_threshold", &self.straighten_threshold)
+            .field("caption", &self.caption)
+            .field("render_substitutions", &self.render_substitutions)
+            .finish()
+    }
+}
+
+impl fmt::Display for Canvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Eraser strokes only take effect once committed, until then showing what they'd remove
+        let erase_preview = (self.tool == Tool::Erase).then(|| self.erase_preview());
+        let sketch = erase_preview.as_ref().unwrap_or(&self.sketch);
+        let overlays = iter::once(sketch).chain(self.outline.iter());
+        self.design.iter().chain(overlays).try_for_each(|segment| {
+            let substituted = (!self.render_substitutions.is_empty())
+                .then(|| segment.substituted(&self.render_substitutions));
+            let segment = substituted.as_ref().unwrap_or(segment);
+            write!(f, "{}", self.to_screen(segment))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn stroke(cells: &[(u16, u16)]) -> grid::Segment {
+        cells.iter().map(|(x, y)| grid::Cell::new((*x, *y).into(), '*')).collect()
+    }
+
+    fn glyphs(cells: &[(u16, u16, char)]) -> grid::Segment {
+        cells.iter().map(|(x, y, glyph)| grid::Cell::new((*x, *y).into(), *glyph)).collect()
+    }
+
+    fn draw_freehand(canvas: &mut Canvas, cells: &[(u16, u16)]) {
+        canvas.sketch_extend(stroke(cells));
+        canvas.commit_sketch().unwrap();
+    }
+
+    #[test]
+    fn export_includes_caption() {
+        let mut canvas = Canvas::new();
+        canvas.set_caption(Some("Plan".to_string()));
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+        assert_eq!(canvas.export(&export::Text), "Plan\n\n*\n");
+    }
+
+    #[test]
+    fn guides_render_but_stay_out_of_exports() {
+        let mut canvas = Canvas::new();
+        canvas.add_guide(Orientation::Vertical, 5);
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+        assert!(canvas.to_ansi().contains(&format!("{}:", grid::Point::new(5, 1))));
+        assert_eq!(canvas.export(&export::Text), "*\n");
+    }
+
+    #[test]
+    fn repeat_last_copies_shifted_stroke() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap();
+        canvas.repeat_last(3, 1).unwrap();
+        assert_eq!(canvas.strokes()[1].cells(), stroke(&[(4, 2), (5, 2)]).cells());
+        assert!(canvas.repeat_last(-10, 0).is_err());
+    }
+
+    #[test]
+    fn custom_exporter_gets_the_drawing() {
+        struct CellCount;
+        impl export::Exporter for CellCount {
+            fn export(&self, content: &grid::Segment) -> String {
+                content.len().to_string()
+            }
+        }
+
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap();
+        canvas.add(stroke(&[(5, 5)])).unwrap();
+        assert_eq!(canvas.export(&CellCount), "3");
+    }
+
+    #[test]
+    fn presses_outside_canvas_are_not_contained() {
+        let canvas = Canvas::auto(|| (80, 24), 3);
+        for pos in &[(0, 0), (1, 3), (81, 10)] {
+            assert!(!canvas.contains(canvas.canvas_point((*pos).into())));
+        }
+        assert!(canvas.contains(canvas.canvas_point((1, 4).into())));
+    }
+
+    #[test]
+    fn faster_drags_pick_later_ramp_glyphs() {
+        let mut canvas = Canvas::new();
+        canvas.set_speed_glyphs(vec!['#', '*', '.']);
+        assert_eq!(canvas.speed_glyph((1, 1).into(), (2, 1).into()), Some('#'));
+        assert_eq!(canvas.speed_glyph((1, 1).into(), (3, 1).into()), Some('*'));
+        assert_eq!(canvas.speed_glyph((1, 1).into(), (20, 1).into()), Some('.'));
+
+        canvas.set_speed_glyphs(Vec::new());
+        assert_eq!(canvas.speed_glyph((1, 1).into(), (20, 1).into()), None);
+    }
+
+    #[test]
+    fn duplicate_mirrored_reflects_across_axis() {
+        let mut canvas = Canvas::new();
+        canvas.add(glyphs(&[(2, 1, '/'), (3, 2, '<')])).unwrap();
+        canvas.duplicate_mirrored(5).unwrap();
+
+        let mirrored = glyphs(&[(8, 1, '\\'), (7, 2, '>')]);
+        assert_eq!(canvas.strokes()[1].cells(), mirrored.cells());
+    }
+
+    #[test]
+    fn outline_surrounds_stroke_until_cleared() {
+        let tracer = grid::Tracer::default();
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(3, 3), (5, 4)])).unwrap();
+        canvas.outline_segment(&tracer, 0).unwrap();
+
+        let outline = canvas.clear_outline().unwrap();
+        assert_eq!(outline.boundaries(), Some(((2, 2).into(), (6, 5).into())));
+        assert!(canvas.clear_outline().is_none());
+        assert!(canvas.outline_segment(&tracer, 1).is_err());
+    }
+
+    #[test]
+    fn cycle_tool_wraps_around() {
+        let mut canvas = Canvas::new();
+        let tools: Vec<Tool> = (0..4).map(|_| canvas.cycle_tool()).collect();
+        assert_eq!(tools, vec![Tool::Line, Tool::Erase, Tool::Measure, Tool::Plot]);
+    }
+
+    #[test]
+    fn lasso_selects_visible_cells_in_loop() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(3, 3), (8, 8), (4, 4)])).unwrap();
+        let mut eraser = stroke(&[(4, 4)]);
+        eraser.fill(' ');
+        canvas.erase(eraser).unwrap();
+
+        let path: Vec<grid::Point> =
+            [(1, 1), (6, 1), (6, 6), (1, 6)].iter().map(|pos| (*pos).into()).collect();
+        assert_eq!(canvas.lasso(&path).cells(), stroke(&[(3, 3)]).cells());
+    }
+
+    #[test]
+    fn stats_summarize_drawing() {
+        let mut canvas = Canvas::new();
+        canvas.add(glyphs(&[(2, 2, 'a'), (4, 3, 'b')])).unwrap();
+        canvas.add(glyphs(&[(3, 5, 'a')])).unwrap();
+
+        let stats = canvas.stats();
+        let bounds = Some(((2, 2).into(), (4, 5).into()));
+        assert_eq!(stats, CanvasStats { strokes: 2, total_cells: 3, bounds, distinct_glyphs: 2 });
+        assert_eq!(stats.to_string(), "Strokes: 2, Cells: 3, Size: 3x4, Glyphs: 2");
+    }
+
+    #[test]
+    fn erase_glyph_removes_every_match() {
+        let mut canvas = Canvas::new();
+        canvas.add(glyphs(&[(1, 1, '.'), (2, 1, '#'), (3, 1, '.')])).unwrap();
+        canvas.erase_glyph('.').unwrap();
+        assert_eq!(canvas.export(&export::Text), "#\n");
+        assert!(canvas.erase_glyph('.').is_err());
+    }
+
+    #[test]
+    fn each_command_undoes_and_redoes() {
+        type Step = Box<dyn Fn(&mut Canvas)>;
+        let tracer = grid::Tracer::default();
+        let steps: Vec<Step> = vec![
+            Box::new(|canvas| canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap()),
+            Box::new(|canvas| draw_freehand(canvas, &[(1, 3), (2, 3), (3, 4), (4, 3), (5, 3)])),
+            Box::new(move |canvas| assert!(canvas.shapify_last(&tracer).unwrap().is_some())),
+            Box::new(|canvas| canvas.rotate_all().unwrap()),
+            Box::new(|canvas| {
+                let delta = StrokeDelta::new(canvas.strokes()[0].clone(), StrokeOp::Remove);
+                assert!(canvas.apply_delta(delta).is_some());
+            }),
+            Box::new(|canvas| canvas.clear()),
+        ];
+
+        let mut canvas = Canvas::new();
+        let mut snapshots = vec![canvas.export(&export::Text)];
+        for step in &steps {
+            step(&mut canvas);
+            snapshots.push(canvas.export(&export::Text));
+        }
+        assert!(matches!(
+            canvas.history(),
+            [
+                Command::AddStroke { .. },
+                Command::AddStroke { .. },
+                Command::ReplaceStroke { .. },
+                Command::ReplaceAll { .. },
+                Command::RemoveStroke { .. },
+                Command::ClearAll { .. }
+            ]
+        ));
+
+        for expected in snapshots.iter().rev().skip(1) {
+            assert!(canvas.undo().is_some());
+            assert_eq!(&canvas.export(&export::Text), expected);
+        }
+        assert!(canvas.undo().is_none());
+        for expected in snapshots.iter().skip(1) {
+            assert!(canvas.redo().is_some());
+            assert_eq!(&canvas.export(&export::Text), expected);
+        }
+        assert!(canvas.redo().is_none());
+    }
+
+    #[test]
+    fn cursor_marker_shows_in_frame_only() {
+        let mut canvas = Canvas::new();
+        canvas.cursor = (4, 2).into();
+        canvas.set_cursor_marker(Some(('+', Default::default())));
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+        assert!(canvas.to_ansi().contains(&format!("{}+", grid::Point::new(4, 2))));
+        assert_eq!(canvas.export(&export::Text), "*\n");
+    }
+
+    #[test]
+    fn committed_sketch_becomes_one_stroke() {
+        let mut canvas = Canvas::new();
+        canvas.sketch_add(grid::Cell::new((1, 1).into(), 'a'));
+        canvas.sketch_add(grid::Cell::new((2, 1).into(), 'b'));
+        canvas.commit_sketch().unwrap();
+
+        let drawn = glyphs(&[(1, 1, 'a'), (2, 1, 'b')]);
+        assert_eq!(canvas.strokes().len(), 1);
+        assert_eq!(canvas.strokes()[0].cells(), drawn.cells());
+        assert!(canvas.sketch.is_empty());
+    }
+
+    #[test]
+    fn light_theme_draws_with_dark_pen() {
+        let mut canvas = Canvas::new();
+        canvas.set_theme(Theme::Light);
+        canvas.sketch_add(grid::Cell::new((1, 1).into(), '*'));
+        canvas.commit_sketch().unwrap();
+        assert_eq!(canvas.strokes()[0].format().fg_color, terminal::Color::Black);
+        assert_eq!(Theme::Dark.pen_format().fg_color, terminal::Color::Reset);
+    }
+
+    #[test]
+    fn clip_keeps_cells_inside_rectangle() {
+        let mut canvas = Canvas::new();
+        canvas.set_clip(Some(((3, 1).into(), (5, 5).into())));
+        canvas.add(stroke(&[(1, 2), (3, 2), (5, 2), (7, 2)])).unwrap();
+        assert_eq!(canvas.strokes()[0].cells(), stroke(&[(3, 2), (5, 2)]).cells());
+    }
+
+    #[test]
+    fn label_is_centered_in_rectangle() {
+        let mut canvas = Canvas::new();
+        canvas.place_label_centered("ab", ((1, 1).into(), (10, 5).into())).unwrap();
+        assert_eq!(canvas.strokes()[0].boundaries(), Some(((5, 3).into(), (6, 3).into())));
+    }
+
+    #[test]
+    fn substitutions_change_rendering_but_not_strokes() {
+        let mut canvas = Canvas::new();
+        canvas.add(glyphs(&[(1, 1, '+')])).unwrap();
+        assert_eq!(canvas.export(&export::Text), "+\n");
+
+        canvas.set_render_substitutions(iter::once(('+', '┼')).collect());
+        assert!(canvas.to_string().contains('┼'));
+        assert_eq!(canvas.export(&export::Text), "┼\n");
+        assert_eq!(canvas.strokes()[0].cells()[0].content(), '+');
+    }
+
+    #[test]
+    fn undo_cells_backs_off_the_sketch() {
+        let mut canvas = Canvas::new();
+        canvas.sketch_extend(stroke(&[(1, 1), (2, 1), (3, 1), (4, 1), (5, 1)]));
+        assert_eq!(canvas.undo_cells(2).unwrap().len(), 2);
+        assert_eq!(canvas.sketch.cells(), stroke(&[(1, 1), (2, 1), (3, 1)]).cells());
+        assert!(Canvas::new().undo_cells(1).is_err());
+    }
+
+    #[test]
+    fn preview_shrinks_occupied_blocks() {
+        let mut canvas = Canvas::new();
+        let block: Vec<(u16, u16)> = (1..=4).flat_map(|y| (1..=4).map(move |x| (x, y))).collect();
+        canvas.add(stroke(&block)).unwrap();
+        assert_eq!(canvas.export_preview(2, 2), "**\n**\n");
+    }
+
+    #[test]
+    fn rotate_all_turns_lines_and_glyphs() {
+        let mut canvas = Canvas::new();
+        canvas.add(grid::Tracer::default().trace((1, 1).into(), (4, 1).into())).unwrap();
+        canvas.rotate_all().unwrap();
+
+        let cells = canvas.strokes()[0].cells();
+        assert_eq!(cells.len(), 3);
+        assert!(cells.iter().all(|cell| cell.pos().x == cells[0].pos().x && cell.content() == '|'));
+    }
+
+    #[test]
+    fn components_group_touching_cells() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 2)])).unwrap();
+        canvas.add(stroke(&[(3, 3)])).unwrap();
+        canvas.add(stroke(&[(10, 10)])).unwrap();
+
+        let sizes: Vec<usize> =
+            canvas.components().iter().map(|component| component.len()).collect();
+        assert_eq!(sizes.len(), 2);
+        assert!(sizes.contains(&3) && sizes.contains(&1));
+    }
+
+    #[test]
+    fn imported_table_aligns_columns() {
+        let mut canvas = Canvas::new();
+        let rows: Vec<Vec<String>> =
+            vec![vec!["a".to_string(), "b".to_string()], vec!["long".to_string(), "c".to_string()]];
+        canvas.import_table(&rows, (1, 1).into()).unwrap();
+        assert_eq!(canvas.export(&export::Text), "a    | b\n-----+--\nlong | c\n");
+    }
+
+    #[test]
+    fn imported_table_uses_tab_width() {
+        let mut canvas = Canvas::new();
+        canvas.set_tab_width(2);
+        let rows: Vec<Vec<String>> = vec![vec!["\ta".to_string(), "b".to_string()]];
+        canvas.import_table(&rows, (1, 1).into()).unwrap();
+        assert_eq!(canvas.export(&export::Text), "  a | b\n----+--\n");
+    }
+
+    #[test]
+    fn merging_disjoint_strokes_combines_cells() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap();
+        canvas.add(stroke(&[(5, 5)])).unwrap();
+        assert!(canvas.can_merge(0, 1));
+        canvas.merge_strokes(0, 1).unwrap();
+        assert_eq!(canvas.strokes().len(), 1);
+        assert_eq!(canvas.strokes()[0].len(), 3);
+
+        canvas.add(stroke(&[(5, 5)])).unwrap();
+        assert!(!canvas.can_merge(0, 1));
+        assert!(canvas.merge_strokes(0, 1).is_err());
+    }
+
+    #[test]
+    fn merging_differently_colored_strokes_fails() {
+        let mut canvas = Canvas::new();
+        let mut red = stroke(&[(1, 1)]);
+        red.set_format(terminal::Format::new(terminal::Color::Reset, terminal::Color::Red));
+        canvas.add(red).unwrap();
+        canvas.add(stroke(&[(5, 5)])).unwrap();
+        assert!(canvas.merge_strokes(0, 1).is_err());
+        assert_eq!(canvas.strokes().len(), 2);
+    }
+
+    #[test]
+    fn measurement_spans_drag() {
+        let measurement = Measurement::new((1, 1).into(), (4, 5).into());
+        assert_eq!(measurement, Measurement { manhattan: 7, euclidean: 5.0, width: 4, height: 5 });
+        assert_eq!(measurement.to_string(), "Manhattan: 7, Euclidean: 5.00, Size: 4x5");
+    }
+
+    #[test]
+    fn replace_glyph_changes_only_matches() {
+        let mut canvas = Canvas::new();
+        canvas.add(glyphs(&[(1, 1, '*'), (2, 1, '-'), (3, 1, '*')])).unwrap();
+        canvas.replace_glyph('*', '#').unwrap();
+        assert_eq!(canvas.export(&export::Text), "#-#\n");
+        assert!(canvas.replace_glyph('x', '#').is_err());
+    }
+
+    #[test]
+    fn replace_glyph_ignores_blanks_and_hidden_cells() {
+        let mut canvas = Canvas::new();
+        canvas.add(glyphs(&[(1, 1, '*'), (2, 1, '-')])).unwrap();
+        assert!(canvas.replace_glyph(' ', '#').is_err());
+
+        canvas.erase(glyphs(&[(1, 1, ' ')])).unwrap();
+        assert!(canvas.replace_glyph('*', '#').is_err());
+        assert_eq!(canvas.history().len(), 2);
+    }
+
+    #[test]
+    fn stroke_cells_reads_a_stroke() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 2)])).unwrap();
+        assert_eq!(canvas.stroke_cells(0), Some(stroke(&[(1, 1), (2, 2)]).cells()));
+        assert_eq!(canvas.stroke_cells(1), None);
+    }
+
+    #[test]
+    fn stamp_along_places_spaced_copies() {
+        let mut canvas = Canvas::new();
+        canvas.stamp_along(&stroke(&[(9, 9)]), (1, 1).into(), (6, 1).into(), 2).unwrap();
+        assert_eq!(canvas.strokes()[0].cells(), stroke(&[(1, 1), (3, 1), (5, 1)]).cells());
+    }
+
+    #[test]
+    fn geometry_snap_pulls_to_nearby_corners() {
+        let mut canvas = Canvas::new();
+        canvas.set_geometry_snap(true);
+        canvas.add(stroke(&[(5, 5), (10, 8)])).unwrap();
+        assert_eq!(canvas.snap((11, 9).into()), grid::Point::new(10, 8));
+        assert_eq!(canvas.geometry_snap((30, 20).into()), None);
+    }
+
+    #[test]
+    fn animated_drawing_ends_like_add() {
+        let mut canvas = Canvas::new();
+        let mut out = Vec::new();
+        let segment = stroke(&[(1, 1), (2, 1)]);
+        let delay = time::Duration::from_millis(5);
+        let mut pauses = Vec::new();
+        canvas
+            .draw_segment_animated(&mut out, segment.clone(), delay, |pause| pauses.push(pause))
+            .unwrap();
+        assert_eq!(canvas.strokes()[0].cells(), segment.cells());
+        assert_eq!(pauses, vec![delay, delay]);
+        assert!(String::from_utf8(out).unwrap().contains('*'));
+    }
+
+    #[test]
+    fn clamp_keeps_drags_on_canvas() {
+        let canvas = Canvas::new();
+        assert_eq!(canvas.clamp((0, 0).into()), grid::Point::new(1, 1));
+        assert_eq!(canvas.clamp((90, 30).into()), grid::Point::new(80, 24));
+        assert_eq!(canvas.clamp((7, 9).into()), grid::Point::new(7, 9));
+    }
+
+    #[test]
+    fn banner_draws_large_letters() {
+        let mut canvas = Canvas::new();
+        canvas.banner("A", (1, 1).into()).unwrap();
+        assert_eq!(canvas.export(&export::Text), " _ \n|_|\n| |\n");
+        assert!(canvas.banner("?", (1, 1).into()).is_err());
+    }
+
+    #[test]
+    fn cells_in_lists_visible_cells_in_rectangle() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (3, 3), (5, 5), (4, 2)])).unwrap();
+        let cells = canvas.cells_in((2, 2).into(), (4, 4).into());
+        assert_eq!(cells, stroke(&[(4, 2), (3, 3)]).cells());
+    }
+
+    #[test]
+    fn chained_lines_share_endpoints() {
+        let mut canvas = Canvas::new();
+        canvas.set_tool(Tool::Line);
+        canvas.set_line_chaining(true);
+        let start = canvas.line_start((1, 1).into());
+        canvas.sketch_extend(grid::Tracer::default().trace(start, (5, 1).into()));
+        canvas.commit_sketch().unwrap();
+        assert_eq!(canvas.line_start((9, 9).into()), grid::Point::new(5, 1));
+
+        canvas.set_line_chaining(false);
+        assert_eq!(canvas.line_start((9, 9).into()), grid::Point::new(9, 9));
+    }
+
+    #[test]
+    fn frame_surrounds_drawing_with_padding() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(3, 3), (5, 4)])).unwrap();
+        canvas.frame(&grid::Tracer::default(), 1).unwrap();
+        assert_eq!(canvas.strokes()[1].boundaries(), Some(((2, 2).into(), (6, 5).into())));
+    }
+
+    #[test]
+    fn erase_preview_marks_cells_until_release() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 1), (3, 1)])).unwrap();
+        canvas.set_tool(Tool::Erase);
+        canvas.sketch_add(grid::Cell::new((2, 1).into(), ' '));
+        canvas.sketch_add(grid::Cell::new((9, 9).into(), ' '));
+
+        assert_eq!(canvas.erase_preview().cells(), stroke(&[(2, 1)]).cells());
+        assert_eq!(canvas.export(&export::Text), "***\n");
+        canvas.commit_sketch().unwrap();
+        assert_eq!(canvas.export(&export::Text), "* *\n");
+    }
+
+    #[test]
+    fn declutter_separates_overlapping_strokes() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(5, 5), (6, 5)])).unwrap();
+        canvas.add(stroke(&[(6, 5), (7, 5)])).unwrap();
+        canvas.declutter(0).unwrap();
+        assert!(!canvas.strokes()[0].intersects(&canvas.strokes()[1]));
+    }
+
+    #[test]
+    fn declutter_leaves_erased_strokes_in_place() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(5, 5), (6, 5)])).unwrap();
+        canvas.add(stroke(&[(6, 5), (7, 5), (8, 5)])).unwrap();
+        canvas.erase(glyphs(&[(8, 5, ' ')])).unwrap();
+        let before = canvas.export(&export::Text);
+        canvas.declutter(0).unwrap();
+        assert_eq!(canvas.export(&export::Text), before);
+        assert_eq!(canvas.history().len(), 3);
+    }
+
+    #[test]
+    fn markdown_fence_outgrows_backticks_in_drawing() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+        assert_eq!(canvas.export_markdown(None), "```\n*\n```");
+
+        let mut canvas = Canvas::new();
+        canvas.add(grid::Segment::from_str((1, 1).into(), "```", Default::default())).unwrap();
+        assert_eq!(canvas.export_markdown(Some("text")), "````text\n```\n````");
+    }
+
+    #[test]
+    fn svg_line_becomes_scaled_stroke() {
+        let mut canvas = Canvas::new();
+        let svg = r#"<svg><line x1="0" y1="0" x2="2" y2="1"/></svg>"#;
+        canvas.import_svg_lines(&grid::Tracer::default(), svg, (5, 5).into(), 2.0).unwrap();
+
+        let (first, last) = canvas.strokes()[0].endpoints().unwrap();
+        assert!(first.distance((5, 5).into()) < 1.5);
+        assert_eq!(last, grid::Point::new(9, 7));
+        assert!(canvas
+            .import_svg_lines(&grid::Tracer::default(), "<svg/>", (5, 5).into(), 2.0)
+            .is_err());
+    }
+
+    #[test]
+    fn graph_mode_snaps_to_paper_marks() {
+        let mut canvas = Canvas::new();
+        canvas.set_graph_mode(Some(4));
+        assert_eq!(canvas.snap((5, 7).into()), grid::Point::new(4, 8));
+
+        let paper = canvas.graph_paper().unwrap();
+        assert!(paper.cells().iter().all(|cell| cell.pos().x % 4 == 0 && cell.pos().y % 4 == 0));
+        assert!(paper.cells().iter().any(|cell| cell.pos() == grid::Point::new(4, 4)));
+
+        canvas.set_graph_mode(None);
+        assert!(canvas.graph_paper().is_none());
+    }
+
+    #[test]
+    fn jump_to_moves_through_history() {
+        let mut canvas = Canvas::new();
+        for x in 1..=3 {
+            canvas.add(stroke(&[(x, 1)])).unwrap();
+        }
+
+        canvas.jump_to(1).unwrap();
+        assert_eq!((canvas.history().len(), canvas.redo_stack().len()), (1, 2));
+        assert_eq!(canvas.export(&export::Text), "*\n");
+        canvas.jump_to(3).unwrap();
+        assert_eq!(canvas.export(&export::Text), "***\n");
+        assert!(canvas.jump_to(4).is_err());
+    }
+
+    #[test]
+    fn nib_thickens_strokes_across_it() {
+        let mut canvas = Canvas::new();
+        canvas.set_nib_angle(Some(90.0));
+        let across = canvas.nibbed(stroke(&[(5, 5)]), (4, 5).into(), (5, 5).into());
+        assert_eq!(across.len(), 3);
+        let along = canvas.nibbed(stroke(&[(5, 5)]), (5, 4).into(), (5, 5).into());
+        assert_eq!(along.len(), 1);
+    }
+
+    #[test]
+    fn preview_listener_gets_line_geometry() {
+        let previews = Rc::new(RefCell::new(Vec::new()));
+        let mut canvas = Canvas::new();
+        let seen = Rc::clone(&previews);
+        canvas.on_preview(move |preview| seen.borrow_mut().push(*preview));
+        canvas.cursor = (1, 1).into();
+        canvas.preview_line(&grid::Tracer::default(), (4, 4).into());
+
+        let previews = previews.borrow();
+        assert_eq!(previews.len(), 1);
+        assert_eq!((previews[0].from, previews[0].to), ((1, 1).into(), (4, 4).into()));
+        assert!((previews[0].length - 3.0 * 2f64.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sketch_shows_stroke_in_progress() {
+        let mut canvas = Canvas::new();
+        canvas.sketch_extend(stroke(&[(1, 1), (2, 1)]));
+        canvas.sketch_add(grid::Cell::new((3, 1).into(), '*'));
+        assert_eq!(canvas.sketch().cells(), stroke(&[(1, 1), (2, 1), (3, 1)]).cells());
+        assert!(canvas.strokes().is_empty());
+    }
+
+    #[test]
+    fn density_export_shades_by_fill() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 1), (1, 2), (2, 2), (5, 1)])).unwrap();
+        assert_eq!(canvas.export_density(2), "@ -\n");
+    }
+
+    #[test]
+    fn compact_history_drops_erased_strokes() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+        canvas.add(stroke(&[(3, 3)])).unwrap();
+        let mut eraser = stroke(&[(1, 1)]);
+        eraser.fill(' ');
+        canvas.erase(eraser).unwrap();
+
+        canvas.compact_history();
+        assert_eq!(canvas.strokes().len(), 1);
+        assert_eq!(canvas.strokes()[0].cells(), stroke(&[(3, 3)]).cells());
+    }
+
+    #[test]
+    fn delta_reproduces_stroke_on_another_canvas() {
+        let deltas = Rc::new(RefCell::new(Vec::new()));
+        let mut local = Canvas::new();
+        let sent = Rc::clone(&deltas);
+        local.on_commit(move |delta| sent.borrow_mut().push(delta.clone()));
+
+        let mut remote = Canvas::new();
+        local.add(stroke(&[(1, 1), (2, 2)])).unwrap();
+        remote.add(stroke(&[(5, 5)])).unwrap();
+        local.add(stroke(&[(3, 3)])).unwrap();
+        local.undo();
+        for delta in deltas.borrow_mut().drain(..) {
+            remote.apply_delta(delta);
+        }
+
+        // The undone stroke is removed by its cells, not by being the remote's latest
+        let cells: Vec<&[grid::Cell]> = remote.strokes().iter().map(|s| s.cells()).collect();
+        assert_eq!(cells, vec![stroke(&[(5, 5)]).cells(), stroke(&[(1, 1), (2, 2)]).cells()]);
+    }
+
+    #[test]
+    fn remove_delta_without_match_does_nothing() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+
+        let delta = StrokeDelta::new(stroke(&[(9, 9)]), StrokeOp::Remove);
+        assert!(canvas.apply_delta(delta).is_none());
+        assert_eq!(canvas.strokes().len(), 1);
+        assert_eq!(canvas.history().len(), 1);
+    }
+
+    #[test]
+    fn erasing_spares_locked_strokes() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap();
+        canvas.set_locked(0, true).unwrap();
+
+        let mut eraser = stroke(&[(1, 1), (2, 1), (3, 1)]);
+        eraser.fill(' ');
+        canvas.erase(eraser).unwrap();
+        assert_eq!(canvas.strokes()[1].cells(), [grid::Cell::new((3, 1).into(), ' ')]);
+        assert_eq!(canvas.export(&export::Text), "**\n");
+    }
+
+    #[test]
+    fn undoing_an_addition_keeps_its_lock_for_redo() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+        canvas.set_locked(0, true).unwrap();
+
+        canvas.undo();
+        assert!(!canvas.is_locked(0));
+        canvas.redo();
+        assert!(canvas.is_locked(0));
+    }
+
+    #[test]
+    fn clear_keeps_locked_strokes() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+        canvas.add(stroke(&[(2, 2)])).unwrap();
+        canvas.add(stroke(&[(3, 3)])).unwrap();
+        canvas.set_locked(1, true).unwrap();
+
+        canvas.clear();
+        assert_eq!(canvas.strokes().len(), 1);
+        assert_eq!(canvas.strokes()[0].cells(), stroke(&[(2, 2)]).cells());
+        assert!(canvas.is_locked(0));
+
+        canvas.undo();
+        assert_eq!(canvas.strokes().len(), 3);
+        assert!(canvas.is_locked(1) && !canvas.is_locked(0));
+    }
+
+    #[test]
+    fn rotate_all_leaves_locked_strokes() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(1, 1), (2, 1), (3, 1)])).unwrap();
+        canvas.add(stroke(&[(10, 10), (11, 10)])).unwrap();
+        canvas.set_locked(1, true).unwrap();
+
+        canvas.rotate_all().unwrap();
+        assert_eq!(canvas.strokes()[1].cells(), stroke(&[(10, 10), (11, 10)]).cells());
+        let rotated: Vec<grid::Point> =
+            canvas.strokes()[0].cells().iter().map(|cell| cell.pos()).collect();
+        assert!(rotated.iter().all(|pos| pos.x == 1));
+    }
+
+    #[test]
+    fn straighten_replaces_only_nearly_straight_strokes() {
+        let tracer = grid::Tracer::default();
+        let mut canvas = Canvas::new();
+        canvas.set_auto_straighten(Some(1.5));
+
+        canvas.sketch_extend(stroke(&[(2, 1), (3, 1), (4, 2), (5, 1), (6, 1)]));
+        assert!(canvas.straighten_sketch(&tracer, (1, 1).into()).is_some());
+        assert_eq!(canvas.sketch().cells(), tracer.trace((1, 1).into(), (6, 1).into()).cells());
+
+        let curve = stroke(&[(2, 2), (3, 4), (4, 6), (6, 6), (8, 4), (9, 2)]);
+        canvas.replace_sketch(curve.clone());
+        assert!(canvas.straighten_sketch(&tracer, (1, 1).into()).is_none());
+        assert_eq!(canvas.sketch().cells(), curve.cells());
+    }
+
+    #[test]
+    fn straighten_leaves_loops_and_short_strokes() {
+        let tracer = grid::Tracer::default();
+        let mut canvas = Canvas::new();
+        canvas.set_auto_straighten(Some(1.0));
+
+        canvas.replace_sketch(stroke(&[(2, 1), (3, 1), (2, 1), (1, 1)]));
+        assert!(canvas.straighten_sketch(&tracer, (1, 1).into()).is_none());
+        assert_eq!(canvas.sketch().len(), 4);
+
+        canvas.replace_sketch(stroke(&[(2, 1)]));
+        assert!(canvas.straighten_sketch(&tracer, (1, 1).into()).is_none());
+    }
+
+    #[test]
+    fn auto_uses_reported_size() {
+        let mut canvas = Canvas::auto(|| (120, 40), 3);
+        assert_eq!(canvas.bounds(), (grid::Point::new(1, 4), grid::Point::new(120, 40)));
+
+        canvas.fit_to(|| (60, 20));
+        assert_eq!(canvas.bounds(), (grid::Point::new(1, 4), grid::Point::new(60, 20)));
+    }
+
+    #[test]
+    fn colors_used_lists_distinct_drawn_colors() {
+        let mut canvas = Canvas::new();
+        let colors = [
+            terminal::Color::Red,
+            terminal::Color::Reset,
+            terminal::Color::Blue,
+            terminal::Color::Red,
+        ];
+        for (x, color) in (1..).zip(colors.iter()) {
+            let mut segment = stroke(&[(x, 1)]);
+            segment.set_format(terminal::Format::new(terminal::Color::Reset, *color));
+            canvas.add(segment).unwrap();
+        }
+        let mut eraser = stroke(&[(9, 9)]);
+        eraser.fill(' ');
+        eraser.set_format(terminal::Format::new(terminal::Color::Reset, terminal::Color::Green));
+        canvas.erase(eraser).unwrap();
+
+        assert_eq!(canvas.colors_used(), vec![terminal::Color::Red, terminal::Color::Blue]);
+    }
+
+    #[test]
+    fn shapify_turns_rough_rectangle_into_rectangle() {
+        let tracer = grid::Tracer::default();
+        let mut canvas = Canvas::new();
+        let mut rough = Vec::new();
+        rough.extend((1..=8).map(|x| (x, 1)));
+        rough.extend((2..=5).map(|y| (9, y)));
+        rough.extend((1..=8).rev().map(|x| (x, if x == 4 { 5 } else { 6 })));
+        rough.extend((2..=5).rev().map(|y| (1, y)));
+        draw_freehand(&mut canvas, &rough);
+
+        assert!(canvas.shapify_last(&tracer).unwrap().is_some());
+        let rectangle = tracer.rectangle((1, 1).into(), (9, 6).into());
+        assert_eq!(canvas.strokes()[0].cells(), rectangle.cells());
+    }
+
+    #[test]
+    fn shapify_leaves_scribbles() {
+        let tracer = grid::Tracer::default();
+        let mut canvas = Canvas::new();
+        let scribble = [(1, 1), (5, 3), (2, 6), (8, 2), (3, 3), (9, 9)];
+        draw_freehand(&mut canvas, &scribble);
+
+        assert!(canvas.shapify_last(&tracer).unwrap().is_none());
+        assert_eq!(canvas.strokes()[0].cells(), stroke(&scribble).cells());
+    }
+
+    #[test]
+    fn shapify_only_takes_freehand_strokes() {
+        let tracer = grid::Tracer::default();
+        let mut canvas = Canvas::new();
+        canvas.place_label_centered("----", ((1, 1).into(), (4, 1).into())).unwrap();
+        assert!(canvas.shapify_last(&tracer).is_err());
+
+        canvas.set_tool(Tool::Erase);
+        draw_freehand(&mut canvas, &[(1, 1), (2, 1)]);
+        assert!(canvas.shapify_last(&tracer).is_err());
+
+        canvas.set_tool(Tool::Plot);
+        let mut gappy = stroke(&[(1, 3), (2, 3), (3, 3), (4, 3)]);
+        gappy.add(grid::Cell::new((5, 3).into(), ' '));
+        canvas.sketch_extend(gappy);
+        canvas.commit_sketch().unwrap();
+        assert!(canvas.shapify_last(&tracer).unwrap().is_none());
+    }
+
+    #[test]
+    fn drawing_past_cell_cap_fails() {
+        let mut canvas = Canvas::new();
+        canvas.set_cell_cap(Some(3));
+        canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap();
+
+        let error = canvas.add(stroke(&[(3, 1), (4, 1)])).unwrap_err();
+        assert_eq!(error.to_string(), "drawing is limited to 3 cells");
+        assert_eq!(canvas.strokes().len(), 1);
+        canvas.add(stroke(&[(3, 1)])).unwrap();
+    }
+
+    #[test]
+    fn erasing_is_exempt_from_cell_cap() {
+        let mut canvas = Canvas::new();
+        canvas.set_cell_cap(Some(2));
+        canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap();
+
+        let mut eraser = stroke(&[(1, 1), (2, 1), (3, 1)]);
+        eraser.fill(' ');
+        canvas.erase(eraser).unwrap();
+        assert_eq!(canvas.strokes().len(), 2);
+        assert!(canvas.add(stroke(&[(5, 5)])).is_err());
+    }
+
+    #[test]
+    fn refresh_rewrites_every_cell() {
+        let mut canvas = Canvas::new();
+        canvas.add(stroke(&[(2, 2), (7, 4)])).unwrap();
+        canvas.add(stroke(&[(20, 10)])).unwrap();
+        canvas.sketch_add(grid::Cell::new((40, 12).into(), '*'));
+
+        let mut out = Vec::new();
+        canvas.refresh(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        for pos in &[(2, 2), (7, 4), (20, 10), (40, 12)] {
+            assert!(out.contains(&format!("{}*", grid::Point::from(*pos))), "{:?}", pos);
+        }
+        // Blanks wipe the rest of the canvas first
+        assert!(out.contains(&format!("{} ", grid::Point::new(1, 1))));
+        assert!(out.contains(&format!("{} ", grid::Point::new(1, 24))));
+    }
+
+    #[test]
+    fn origin_moves_drawing_on_screen() {
+        let mut canvas = Canvas::new();
+        canvas.set_origin((10, 5).into());
+        canvas.add(stroke(&[(1, 1)])).unwrap();
+
+        let drawn = canvas.to_string();
+        assert!(drawn.contains(&grid::Point::new(10, 5).to_string()));
+        assert!(!drawn.contains(&grid::Point::new(1, 1).to_string()));
+    }
+
+    #[test]
+    fn origin_moves_segments_returned_for_erasing() {
+        let mut canvas = Canvas::new();
+        canvas.set_origin((10, 5).into());
+        canvas.add(stroke(&[(1, 1), (2, 1)])).unwrap();
+
+        let removed = canvas.undo().unwrap();
+        assert_eq!(removed.cells(), stroke(&[(10, 5), (11, 5)]).cells());
+
+        canvas.sketch_extend(stroke(&[(3, 3)]));
+        let removed = canvas.undo_cells(1).unwrap();
+        assert_eq!(removed.cells(), stroke(&[(12, 7)]).cells());
+    }
+
+    #[test]
+    fn canvas_point_undoes_origin() {
+        let mut canvas = Canvas::new();
+        canvas.set_origin((10, 5).into());
+
+        assert_eq!(canvas.canvas_point((10, 5).into()), grid::Point::new(1, 1));
+        assert_eq!(canvas.canvas_point((12, 9).into()), grid::Point::new(3, 5));
+        assert!(!canvas.contains(canvas.canvas_point((3, 3).into())));
+    }
+
+    #[test]
+    fn to_ansi_moves_overlays_with_origin() {
+        let mut canvas = Canvas::new();
+        canvas.set_origin((10, 5).into());
+        canvas.add_guide(Orientation::Horizontal, 1);
+        canvas.set_cursor_marker(Some(('+', terminal::RESET_FORMAT)));
+        canvas.set_graph_mode(Some(4));
+
+        let frame = canvas.to_ansi();
+        // The guide starts and the cursor marker sits at the canvas's top left corner, with the
+        // first graph paper node three cells further in
+        assert!(frame.contains(&grid::Point::new(10, 5).to_string()));
+        assert!(frame.contains(&grid::Point::new(13, 8).to_string()));
+        assert!(!frame.contains(&grid::Point::new(1, 1).to_string()));
+        assert!(!frame.contains(&grid::Point::new(4, 4).to_string()));
     }
 }
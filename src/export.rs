@@ -1,22 +1,186 @@
+use std::cmp;
 use std::fs;
 use std::io::Write;
 use std::path;
+use std::slice;
 use std::time;
 
 use terminal::grid;
 
-pub fn to_file(blueprint: grid::Segment) -> crate::Result<String> {
+#[rustfmt::skip]
+static ADDED_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::Reset,
+    fg_color: terminal::Color::Green,
+};
+
+#[rustfmt::skip]
+static REMOVED_FORMAT: terminal::Format = terminal::Format {
+    bg_color: terminal::Color::Reset,
+    fg_color: terminal::Color::Red,
+};
+
+pub trait Exporter {
+    fn export(&self, content: &grid::Segment) -> String;
+}
+
+/// Plain text, with the content cropped to its boundaries.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Text;
+
+impl Exporter for Text {
+    fn export(&self, content: &grid::Segment) -> String {
+        content.trimmed().into()
+    }
+}
+
+/// Plain text in a Markdown fenced code block, with an optional language tag. The fence is made
+/// longer than any run of backticks in the content, so that it can't close early.
+#[derive(Debug, Default, Clone)]
+pub struct Markdown {
+    lang: Option<String>,
+}
+
+impl Markdown {
+    pub fn new(lang: Option<&str>) -> Self {
+        Self { lang: lang.map(String::from) }
+    }
+}
+
+impl Exporter for Markdown {
+    fn export(&self, content: &grid::Segment) -> String {
+        let text = Text.export(content);
+        let text = text.trim_end_matches('\n');
+        let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or_default();
+        let fence = "`".repeat(cmp::max(longest_run + 1, 3));
+        format!("{}{}\n{}\n{}", fence, self.lang.as_deref().unwrap_or_default(), text, fence)
+    }
+}
+
+pub fn to_file(content: &str) -> crate::Result<String> {
     let file_name = {
         let time = time::SystemTime::now().duration_since(time::SystemTime::UNIX_EPOCH)?;
         format!("shketch-{}.txt", time.as_millis())
     };
-    to_file_as(blueprint, &file_name)?;
+    to_file_as(content, &file_name)?;
     Ok(file_name)
 }
 
-pub fn to_file_as(blueprint: grid::Segment, file_name: &str) -> crate::Result {
+pub fn to_file_as(content: &str, file_name: &str) -> crate::Result {
     let mut file = fs::File::create(path::Path::new(&file_name))?;
-    let content: String = blueprint.into();
     file.write_all(content.as_bytes())?;
     Ok(())
 }
+
+/// The visible cells two drawings have in common, and those only one of them has.
+#[derive(Debug, Clone, Default)]
+pub struct DiffResult {
+    pub added: Vec<grid::Cell>,
+    pub removed: Vec<grid::Cell>,
+    pub unchanged: Vec<grid::Cell>,
+}
+
+impl DiffResult {
+    /// Returns the escape sequences drawing both drawings over each other from the top left of
+    /// the screen, with added cells in green and removed ones in red.
+    pub fn render(&self) -> String {
+        let segment = |cells: &[grid::Cell], format: terminal::Format| {
+            let mut segment: grid::Segment = cells.iter().copied().collect();
+            segment.set_format(format);
+            segment.to_string()
+        };
+        segment(&self.unchanged, terminal::RESET_FORMAT)
+            + &segment(&self.removed, REMOVED_FORMAT)
+            + &segment(&self.added, ADDED_FORMAT)
+    }
+}
+
+/// Compares two drawings saved as plain text, such as by `to_file`. Both are read from their
+/// first column and line, so drawings whose bounds differ are compared from their top left
+/// corners.
+pub fn diff_files(a: &path::Path, b: &path::Path) -> crate::Result<DiffResult> {
+    let read = |path: &path::Path| -> crate::Result<grid::Segment> {
+        let text = fs::read_to_string(path)?;
+        Ok(grid::Segment::from_str(grid::Point::default(), &text, Default::default()))
+    };
+    let (old, new) = (read(a)?, read(b)?);
+
+    let (added, removed) = grid::diff_snapshots(slice::from_ref(&old), slice::from_ref(&new));
+    let unchanged =
+        new.trimmed().cells().iter().filter(|cell| !added.contains(cell)).copied().collect();
+    Ok(DiffResult { added, removed, unchanged })
+}
+
+/// Asks the terminal to put the content on the system clipboard, using the OSC 52 sequence.
+/// Terminals don't acknowledge it, and those without support silently ignore it, so success only
+/// means that the request was written.
+pub fn to_clipboard<W: Write>(out: &mut W, content: &str) -> crate::Result {
+    write!(out, "\x1B]52;c;{}\x07", base64(content.as_bytes()))?;
+    out.flush()?;
+    Ok(())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let buffer =
+            chunk.iter().enumerate().fold(0u32, |acc, (i, b)| acc | (*b as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(ALPHABET[(buffer >> (18 - i * 6) & 0x3F) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file in the temporary directory, named after the process so that concurrent runs don't
+    /// collide, and removed when dropped.
+    struct TempFile(path::PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, content: &str) -> Self {
+            let file_name = format!("shketch-{}-{}", std::process::id(), name);
+            let path = std::env::temp_dir().join(file_name);
+            fs::write(&path, content).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn diff_files_finds_added_and_removed_cells() {
+        let a = TempFile::new("diff-empty-box.txt", "+--+\n|  |\n+--+\n");
+        let b = TempFile::new("diff-full-box.txt", "+--+\n|ab|\n+--+\n");
+
+        let diff = diff_files(&a.0, &b.0).unwrap();
+        let cell = |x, y, glyph| grid::Cell::new(grid::Point::new(x, y), glyph);
+        assert_eq!(diff.added, vec![cell(2, 2, 'a'), cell(3, 2, 'b')]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.unchanged.len(), 10);
+
+        let diff = diff_files(&b.0, &a.0).unwrap();
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed, vec![cell(2, 2, 'a'), cell(3, 2, 'b')]);
+    }
+
+    #[test]
+    fn to_clipboard_writes_osc_52() {
+        let mut out = Vec::new();
+        to_clipboard(&mut out, "hi!").unwrap();
+        assert_eq!(out, b"\x1B]52;c;aGkh\x07");
+    }
+}
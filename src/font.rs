@@ -0,0 +1,73 @@
+//! A small font drawing letters and digits three rows tall, for banners.
+
+use std::convert::TryFrom;
+
+use terminal::grid;
+
+pub(crate) const HEIGHT: u16 = 3;
+pub(crate) const WIDTH: u16 = 3;
+
+#[rustfmt::skip]
+fn rows(char: char) -> Option<[&'static str; HEIGHT as usize]> {
+    let rows = match char.to_ascii_uppercase() {
+        ' ' => ["   ", "   ", "   "],
+        '0' => [" _ ", "| |", "|_|"],
+        '1' => ["   ", "  |", "  |"],
+        '2' => [" _ ", " _|", "|_ "],
+        '3' => [" _ ", " _|", " _|"],
+        '4' => ["   ", "|_|", "  |"],
+        '5' => [" _ ", "|_ ", " _|"],
+        '6' => [" _ ", "|_ ", "|_|"],
+        '7' => [" _ ", "  |", "  |"],
+        '8' => [" _ ", "|_|", "|_|"],
+        '9' => [" _ ", "|_|", " _|"],
+        'A' => [" _ ", "|_|", "| |"],
+        'B' => ["   ", "|_ ", "|_|"],
+        'C' => [" _ ", "|  ", "|_ "],
+        'D' => ["   ", " _|", "|_|"],
+        'E' => [" _ ", "|_ ", "|_ "],
+        'F' => [" _ ", "|_ ", "|  "],
+        'G' => [" _ ", "|  ", "|_|"],
+        'H' => ["   ", "|_|", "| |"],
+        'I' => ["___", " | ", "_|_"],
+        'J' => ["   ", "  |", "|_|"],
+        'K' => ["   ", "|/ ", "|\\ "],
+        'L' => ["   ", "|  ", "|_ "],
+        'M' => ["   ", "|v|", "| |"],
+        'N' => ["   ", "|\\|", "| |"],
+        'O' => [" _ ", "| |", "|_|"],
+        'P' => [" _ ", "|_|", "|  "],
+        'Q' => [" _ ", "| |", "|_\\"],
+        'R' => [" _ ", "|_|", "|\\ "],
+        'S' => [" _ ", "|_ ", " _|"],
+        'T' => ["___", " | ", " | "],
+        'U' => ["   ", "| |", "|_|"],
+        'V' => ["   ", "| |", "\\_/"],
+        'W' => ["   ", "| |", "|^|"],
+        'X' => ["   ", "\\_/", "/ \\"],
+        'Y' => ["   ", "\\_/", " | "],
+        'Z' => ["__ ", " / ", "/__"],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// Returns the cells drawing the text with its top left corner at `at`, leaving a blank column
+/// between characters. Blanks within characters aren't drawn, so they don't cover anything.
+pub(crate) fn banner(text: &str, at: grid::Point) -> Result<grid::Segment, String> {
+    let mut banner = grid::Segment::new();
+    for (index, char) in text.chars().enumerate() {
+        let rows = rows(char).ok_or_else(|| format!("no banner glyph for '{}'", char))?;
+        let dx =
+            i32::try_from(index).ok().and_then(|index| index.checked_mul(i32::from(WIDTH) + 1));
+        for (dy, row) in rows.iter().enumerate() {
+            for (column, glyph) in row.chars().enumerate().filter(|(_, glyph)| *glyph != ' ') {
+                let pos = dx
+                    .and_then(|dx| at.offset(dx + column as i32, dy as i32))
+                    .ok_or("banner would leave the canvas")?;
+                banner.add(grid::Cell::new(pos, glyph));
+            }
+        }
+    }
+    Ok(banner)
+}
@@ -5,6 +5,8 @@ pub(crate) type Error = Box<dyn error::Error>;
 pub(crate) type Result<T = ()> = result::Result<T, Error>;
 
 pub mod app;
-pub(crate) mod canvas;
-pub(crate) mod export;
+pub mod canvas;
+pub mod export;
+pub(crate) mod font;
 pub(crate) mod menu;
+pub(crate) mod svg;
@@ -1,6 +1,6 @@
 use std::panic;
 
-use shketch::app;
+use shketch::{app, canvas};
 
 fn main() {
     let directions = ["up", "down", "left", "right", "backward_diagonal", "forward_diagonal"];
@@ -36,6 +36,104 @@ ____/  /    /  /       \____   |  \_____/    /
                 })
                 .collect::<Vec<clap::Arg>>(),
         )
+        .arg(
+            clap::Arg::with_name("caption")
+                .short("c")
+                .long("caption")
+                .help("Caption added to exported drawings")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("speed_ramp")
+                .long("speed-ramp")
+                .value_name("glyphs")
+                .help("Plot characters ordered from slowest to fastest drag speed")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("straighten")
+                .long("straighten")
+                .value_name("threshold")
+                .help("Maximum deviation in cells for straightening freehand strokes")
+                .takes_value(true)
+                .validator(|threshold| {
+                    threshold.parse::<f64>().map(|_| ()).map_err(|_| "Must be a number".into())
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("nib")
+                .long("nib")
+                .value_name("degrees")
+                .help("Angle of a calligraphy nib to draw freehand strokes with")
+                .takes_value(true)
+                .validator(|degrees| {
+                    degrees.parse::<f64>().map(|_| ()).map_err(|_| "Must be a number".into())
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("marker")
+                .short("m")
+                .long("marker")
+                .value_name("character")
+                .help("Character marking the cursor position")
+                .takes_value(true)
+                .validator(|c| {
+                    if c.chars().count() != 1 {
+                        Err("Must be a single character".into())
+                    } else {
+                        Ok(())
+                    }
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("cell_cap")
+                .long("cell-cap")
+                .value_name("cells")
+                .help("Maximum number of cells drawn, eraser strokes excluded")
+                .takes_value(true)
+                .validator(|cap| {
+                    cap.parse::<usize>().map(|_| ()).map_err(|_| "Must be a whole number".into())
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("on_exit")
+                .long("on-exit")
+                .value_name("screen")
+                .help("What to leave on screen when exiting")
+                .takes_value(true)
+                .possible_values(&["clear", "leave", "cursor"]),
+        )
+        .arg(
+            clap::Arg::with_name("geometry_snap")
+                .long("geometry-snap")
+                .help("Snap to the corners and edge midpoints of existing strokes"),
+        )
+        .arg(
+            clap::Arg::with_name("clamp_drags")
+                .long("clamp-drags")
+                .help("Keep drawing along the canvas edge when dragging into the toolbar"),
+        )
+        .arg(
+            clap::Arg::with_name("graph")
+                .long("graph")
+                .value_name("spacing")
+                .help("Show graph paper with nodes this many cells apart, and snap to them")
+                .takes_value(true)
+                .validator(|spacing| match spacing.parse::<u16>() {
+                    Ok(spacing) if spacing > 0 => Ok(()),
+                    _ => Err("Must be a positive whole number".into()),
+                }),
+        )
+        .arg(
+            clap::Arg::with_name("chain_lines")
+                .long("chain-lines")
+                .help("Start each line where the previous one ended"),
+        )
+        .arg(
+            clap::Arg::with_name("light")
+                .long("light")
+                .help("Use colors suited to terminals with a light background"),
+        )
         .get_matches();
 
     let char_set = {
@@ -56,8 +154,34 @@ ____/  /    /  /       \____   |  \_____/    /
         set
     };
 
+    let opts = app::Opts::new(char_set)
+        .with_graph_spacing(matches.value_of("graph").and_then(|s| s.parse().ok()))
+        .with_line_chaining(matches.is_present("chain_lines"))
+        .with_caption(matches.value_of("caption").map(String::from))
+        .with_nib_angle(matches.value_of("nib").and_then(|n| n.parse().ok()))
+        .with_straighten_threshold(matches.value_of("straighten").and_then(|t| t.parse().ok()))
+        .with_toolbar_behavior(if matches.is_present("clamp_drags") {
+            canvas::ToolbarBehavior::Clamp
+        } else {
+            canvas::ToolbarBehavior::Ignore
+        })
+        .with_geometry_snap(matches.is_present("geometry_snap"))
+        .with_on_exit(match matches.value_of("on_exit") {
+            Some("clear") => app::OnExit::Clear,
+            Some("leave") => app::OnExit::Leave,
+            _ => app::OnExit::ShowCursorOnly,
+        })
+        .with_cell_cap(matches.value_of("cell_cap").and_then(|c| c.parse().ok()))
+        .with_theme(if matches.is_present("light") {
+            canvas::Theme::Light
+        } else {
+            canvas::Theme::Dark
+        })
+        .with_cursor_marker(matches.value_of("marker").and_then(|m| m.chars().next()))
+        .with_speed_glyphs(matches.value_of("speed_ramp").unwrap_or_default().chars().collect());
+
     let result = panic::catch_unwind(|| {
-        if let Err(error) = app::launch(app::Opts::new(char_set)) {
+        if let Err(error) = app::launch(opts) {
             eprintln!("{}", error);
         }
     });
@@ -14,15 +14,20 @@ static HIGHLIGHT_FORMAT: terminal::Format = terminal::Format {
 pub struct ToolBar {
     actions: grid::Segment,
     tools: HashMap<canvas::Tool, grid::Segment>,
+    caption: grid::Segment,
 }
 
 impl ToolBar {
     pub fn new() -> Self {
-        let actions: grid::Segment = vec![
+        let actions: grid::Segment = [
             str_to_segment((1, 1), "Exit (q)"),
             str_to_segment((15, 1), "Clear (k)"),
             str_to_segment((30, 1), "Undo (u)"),
-            str_to_segment((45, 1), "Save (Ctrl+s)"),
+            str_to_segment((45, 1), "Redo (Ctrl+r)"),
+            str_to_segment((60, 1), "Save (Ctrl+s)"),
+            // Actions that don't fit within 80 columns go below the tools
+            str_to_segment((1, 3), "Copy (Ctrl+c)"),
+            str_to_segment((15, 3), "Redraw (Ctrl+l)"),
         ]
         .iter()
         .sum();
@@ -31,12 +36,17 @@ impl ToolBar {
         tools.insert(canvas::Tool::Plot, str_to_segment((1, 2), "Plot (1)"));
         tools.insert(canvas::Tool::Line, str_to_segment((15, 2), "Line (2)"));
         tools.insert(canvas::Tool::Erase, str_to_segment((30, 2), "Erase (3)"));
+        tools.insert(canvas::Tool::Measure, str_to_segment((45, 2), "Measure (4)"));
 
-        let mut toolbar = Self { actions, tools };
+        let mut toolbar = Self { actions, tools, caption: grid::Segment::new() };
         toolbar.highlight_tool(Default::default());
         toolbar
     }
 
+    pub fn set_caption(&mut self, caption: &str) {
+        self.caption = str_to_segment((60, 2), caption);
+    }
+
     pub fn highlight_tool(&mut self, tool: canvas::Tool) {
         for (menu_tool, segment) in &mut self.tools {
             if *menu_tool == tool {
@@ -54,7 +64,7 @@ impl fmt::Display for ToolBar {
         for segment in self.tools.values() {
             write!(f, "{}", segment)?;
         }
-        Ok(())
+        write!(f, "{}", self.caption)
     }
 }
 
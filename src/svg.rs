@@ -0,0 +1,56 @@
+//! A minimal reader for the straight lines of SVG drawings.
+
+/// Returns the points of each `<line>` and `<polyline>` element, in document order. Other
+/// elements, and transforms or units, are ignored.
+pub(crate) fn polylines(svg: &str) -> Result<Vec<Vec<(f64, f64)>>, String> {
+    let mut polylines = Vec::new();
+    for tag in svg.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        match name {
+            "line" => {
+                let coordinate = |name| {
+                    attribute(tag, name)
+                        .unwrap_or("0")
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid {} in SVG line", name))
+                };
+                polylines.push(vec![
+                    (coordinate("x1")?, coordinate("y1")?),
+                    (coordinate("x2")?, coordinate("y2")?),
+                ]);
+            }
+            "polyline" => {
+                let numbers = attribute(tag, "points")
+                    .unwrap_or_default()
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|number| !number.is_empty())
+                    .map(str::parse::<f64>)
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| "invalid points in SVG polyline")?;
+                if numbers.len() % 2 != 0 {
+                    return Err("odd number of coordinates in SVG polyline".into());
+                }
+                polylines.push(numbers.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+            }
+            _ => {}
+        }
+    }
+    Ok(polylines)
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(index) = rest.find(name) {
+        let (before, after) = rest.split_at(index);
+        rest = &after[name.len()..];
+        if !before.ends_with(char::is_whitespace) {
+            continue;
+        }
+
+        let value = rest.trim_start().strip_prefix('=')?.trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        return value[1..].split(quote).next();
+    }
+    None
+}